@@ -0,0 +1,56 @@
+use mediawiki::Error as MwError;
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a local file failed.
+    Io(PathBuf, io::Error),
+    /// An image could not be decoded or encoded.
+    Image(String, image::ImageError),
+    /// Optimizing a tilesheet image failed.
+    Optimize(PathBuf, oxipng::PngError),
+    /// A request to the wiki failed.
+    Wiki(MwError),
+    /// The wiki answered with something we did not expect.
+    Response(String),
+    /// A tile, file or setting is not valid.
+    Invalid(String),
+    /// The user or one of the run's policies declined to continue.
+    Aborted(String),
+}
+impl Error {
+    /// For use with `map_err`, attaching the path that was being accessed.
+    pub fn io<P: AsRef<Path>>(path: P) -> impl FnOnce(io::Error) -> Error {
+        move |e| Error::Io(path.as_ref().to_owned(), e)
+    }
+    /// For use with `map_err`, attaching the name of the image that was being processed.
+    pub fn image<S: Display>(name: S) -> impl FnOnce(image::ImageError) -> Error {
+        move |e| Error::Image(name.to_string(), e)
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Image(name, e) => write!(f, "failed to process image {name}: {e}"),
+            Error::Optimize(path, e) => write!(f, "failed to optimize {}: {e}", path.display()),
+            Error::Wiki(e) => write!(f, "wiki request failed: {e:?}"),
+            Error::Response(msg) => write!(f, "unexpected response from the wiki: {msg}"),
+            Error::Invalid(msg) => write!(f, "{msg}"),
+            Error::Aborted(reason) => write!(f, "aborted: {reason}"),
+        }
+    }
+}
+impl StdError for Error {}
+impl From<MwError> for Error {
+    fn from(e: MwError) -> Error {
+        Error::Wiki(e)
+    }
+}
@@ -1,16 +1,19 @@
 #![warn(clippy::all)]
 #![allow(clippy::many_single_char_names)]
 
-use image::{ImageBuffer, Rgba, RgbaImage};
+use clap::{value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use error::{Error, Result};
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
 use std::{
-    cmp::Ordering,
-    env::args,
-    fs::{create_dir, File},
-    io::{stdin, Write},
-    path::Path,
+    cmp::{max, min},
+    fs::{create_dir_all, File},
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 use walkdir::WalkDir;
 
+mod error;
 mod tilesheets;
 
 type FloatImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
@@ -70,7 +73,7 @@ impl Linear for Rgba<f32> {
             } else {
                 x.powf(1. / 2.4) * (1. + 0.055) - 0.055
             };
-            (x * 255.).round().max(0.).min(255.) as u8
+            (x * 255.).round().clamp(0., 255.) as u8
         }
         let p = if self[3] > 0.0001 {
             Rgba([
@@ -89,95 +92,812 @@ fn encode_srgb(img: &FloatImage) -> RgbaImage {
     let (w, h) = img.dimensions();
     ImageBuffer::from_fn(w, h, |x, y| img[(x, y)].encode())
 }
-fn resize(img: &FloatImage, width: u32, height: u32) -> FloatImage {
-    let (w, h) = img.dimensions();
-    assert!(width.cmp(&w) == height.cmp(&h));
-    match width.cmp(&w) {
-        Ordering::Less => {
-            let (rw, rh) = (w as f32 / (width as f32), h as f32 / (height as f32));
-            ImageBuffer::from_fn(width, height, |x: u32, y: u32| {
-                let (x1, x2) = ((x as f32 * rw) as u32, ((x + 1) as f32 * rw) as u32);
-                let (y1, y2) = ((y as f32 * rh) as u32, ((y + 1) as f32 * rh) as u32);
-                let (mut r, mut g, mut b, mut a) = (0., 0., 0., 0.);
-                for xx in x1..x2 {
-                    for yy in y1..y2 {
-                        let p = img[(xx, yy)];
-                        r += p[0];
-                        g += p[1];
-                        b += p[2];
-                        a += p[3];
+/// A resampling filter for resizing tiles, applied in premultiplied linear light.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Filter {
+    /// Averages the source pixels each output pixel covers, weighted by how much they overlap.
+    #[default]
+    Box,
+    /// Takes the source pixel under the center of each output pixel, which keeps pixel art crisp.
+    Nearest,
+    /// The Mitchell-Netravali cubic, which is smooth with little ringing.
+    Mitchell,
+    /// A three lobed windowed sinc, which keeps detailed renders sharp.
+    Lanczos3,
+}
+impl Filter {
+    pub fn parse(filter: &str) -> Option<Filter> {
+        match filter {
+            "box" => Some(Filter::Box),
+            "nearest" => Some(Filter::Nearest),
+            "mitchell" => Some(Filter::Mitchell),
+            "lanczos3" => Some(Filter::Lanczos3),
+            _ => None,
+        }
+    }
+    /// How far the kernel reaches from the center, in output pixels.
+    fn support(self) -> f32 {
+        match self {
+            Filter::Box | Filter::Nearest => 0.5,
+            Filter::Mitchell => 2.,
+            Filter::Lanczos3 => 3.,
+        }
+    }
+    fn kernel(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Filter::Box | Filter::Nearest => unreachable!(),
+            Filter::Mitchell => {
+                let (b, c) = (1. / 3., 1. / 3.);
+                if x < 1. {
+                    ((12. - 9. * b - 6. * c) * x.powi(3)
+                        + (-18. + 12. * b + 6. * c) * x.powi(2)
+                        + (6. - 2. * b))
+                        / 6.
+                } else if x < 2. {
+                    ((-b - 6. * c) * x.powi(3)
+                        + (6. * b + 30. * c) * x.powi(2)
+                        + (-12. * b - 48. * c) * x
+                        + (8. * b + 24. * c))
+                        / 6.
+                } else {
+                    0.
+                }
+            }
+            Filter::Lanczos3 => {
+                fn sinc(x: f32) -> f32 {
+                    if x == 0. {
+                        1.
+                    } else {
+                        let x = x * std::f32::consts::PI;
+                        x.sin() / x
                     }
                 }
-                let m = 1. / (((x2 - x1) * (y2 - y1)) as f32);
-                Rgba([r * m, g * m, b * m, a * m])
-            })
+                if x < 3. {
+                    sinc(x) * sinc(x / 3.)
+                } else {
+                    0.
+                }
+            }
         }
-        Ordering::Equal => img.clone(),
-        Ordering::Greater => {
-            let (rw, rh) = (w as f32 / (width as f32), h as f32 / (height as f32));
-            ImageBuffer::from_fn(width, height, |x: u32, y: u32| {
-                let xx = (x as f32 * rw) as u32;
-                let yy = (y as f32 * rh) as u32;
-                img[(xx, yy)]
+    }
+    /// Finds the source pixels along one axis that make up each output pixel, with their weights.
+    fn taps(self, src: u32, dst: u32) -> Vec<Vec<(u32, f32)>> {
+        let scale = src as f32 / dst as f32;
+        let width = scale.max(1.);
+        (0..dst)
+            .map(|i| {
+                if self == Filter::Nearest {
+                    // Integer math so that integer ratios never pick a neighbouring pixel.
+                    let j = (2 * i as u64 + 1) * src as u64 / (2 * dst as u64);
+                    return vec![(j as u32, 1.)];
+                }
+                let center = (i as f32 + 0.5) * scale;
+                // The box covers exactly the output pixel's footprint, so integer enlargements
+                // repeat pixels instead of blending them.
+                let reach = match self {
+                    Filter::Box => scale / 2.,
+                    _ => self.support() * width,
+                };
+                let (lo, hi) = (center - reach, center + reach);
+                let start = lo.floor().max(0.) as u32;
+                let end = (hi.ceil() as u32).min(src);
+                let mut taps = (start..end)
+                    .map(|j| {
+                        let weight = match self {
+                            Filter::Box => (hi.min(j as f32 + 1.) - lo.max(j as f32)).max(0.),
+                            _ => self.kernel((j as f32 + 0.5 - center) / width),
+                        };
+                        (j, weight)
+                    })
+                    .filter(|&(_, weight)| weight != 0.)
+                    .collect::<Vec<_>>();
+                let total = taps.iter().map(|&(_, weight)| weight).sum::<f32>();
+                for (_, weight) in &mut taps {
+                    *weight /= total;
+                }
+                taps
             })
+            .collect()
+    }
+}
+fn sample<F: Fn(u32) -> Rgba<f32>>(taps: &[(u32, f32)], pixel: F) -> Rgba<f32> {
+    let mut sum = [0.; 4];
+    for &(j, weight) in taps {
+        for (s, c) in sum.iter_mut().zip(pixel(j).0) {
+            *s += c * weight;
+        }
+    }
+    Rgba(sum)
+}
+/// Finds the largest block size for which the image is made of uniform square blocks, which is
+/// what pixel art looks like after being enlarged with nearest neighbour.
+fn pixel_grid(img: &FloatImage) -> u32 {
+    let (w, h) = img.dimensions();
+    let (mut a, mut b) = (w, h);
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    (2..=a)
+        .rev()
+        .filter(|block| a % block == 0)
+        .find(|&block| {
+            img.enumerate_pixels()
+                .all(|(x, y, p)| *p == img[(x - x % block, y - y % block)])
+        })
+        .unwrap_or(1)
+}
+/// Resizes an image one axis at a time. Filters with negative lobes can overshoot, so the result
+/// is clamped back to valid premultiplied colors.
+fn resize(img: &FloatImage, width: u32, height: u32, filter: Filter) -> FloatImage {
+    let (w, h) = img.dimensions();
+    if (w, h) == (width, height) {
+        return img.clone();
+    }
+    let columns = filter.taps(w, width);
+    let horizontal: FloatImage = ImageBuffer::from_fn(width, h, |x, y| {
+        sample(&columns[x as usize], |j| img[(j, y)])
+    });
+    let rows = filter.taps(h, height);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let p = sample(&rows[y as usize], |j| horizontal[(x, j)]);
+        let a = p[3].clamp(0., 1.);
+        Rgba([
+            p[0].max(0.).min(a),
+            p[1].max(0.).min(a),
+            p[2].max(0.).min(a),
+            a,
+        ])
+    })
+}
+/// How an image that is not square is made to fit a square tile.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Fit {
+    /// Center the image on a transparent square.
+    #[default]
+    Letterbox,
+    /// Cut the largest square out of the center of the image.
+    Crop,
+    /// Refuse images that are not square.
+    Reject,
+}
+impl Fit {
+    pub fn parse(fit: &str) -> Option<Fit> {
+        match fit {
+            "letterbox" => Some(Fit::Letterbox),
+            "crop" => Some(Fit::Crop),
+            "reject" => Some(Fit::Reject),
+            _ => None,
+        }
+    }
+}
+/// Makes an image square according to the fit policy, returning `None` if it was rejected.
+fn fit_square(img: FloatImage, fit: Fit) -> Option<FloatImage> {
+    let (w, h) = img.dimensions();
+    if w == h {
+        return Some(img);
+    }
+    match fit {
+        Fit::Letterbox => {
+            let size = max(w, h);
+            let (dx, dy) = ((size - w) / 2, (size - h) / 2);
+            let mut square = FloatImage::new(size, size);
+            for (x, y, &p) in img.enumerate_pixels() {
+                square.put_pixel(x + dx, y + dy, p);
+            }
+            Some(square)
+        }
+        Fit::Crop => {
+            let size = min(w, h);
+            let (x, y) = ((w - size) / 2, (h - size) / 2);
+            Some(imageops::crop_imm(&img, x, y, size, size).to_image())
         }
+        Fit::Reject => None,
     }
 }
-#[allow(dead_code)]
-fn shrink() {
-    let _ = create_dir("work/shrunk");
-    for entry in WalkDir::new("work/shrink") {
-        let entry = entry.unwrap();
+/// Settings for shrinking a directory of block renders.
+struct Shrink {
+    input: PathBuf,
+    output: PathBuf,
+    size: u32,
+    /// Images smaller than this are rejected.
+    min_size: u32,
+    /// Prepended to the file name of each shrunk image.
+    prefix: String,
+    filter: Filter,
+    fit: Fit,
+}
+/// Shrinks every image in the input directory, returning whether none of them were rejected.
+fn shrink(options: &Shrink) -> Result<bool> {
+    create_dir_all(&options.output).map_err(Error::io(&options.output))?;
+    let mut rejected = Vec::new();
+    for entry in WalkDir::new(&options.input) {
+        let entry = entry.map_err(|e| {
+            let path = e.path().unwrap_or(&options.input).to_owned();
+            Error::Io(path, e.into())
+        })?;
         let path = entry.path();
         if !path.is_file() {
             continue;
         }
-        let name = path.file_name().unwrap().to_str().unwrap();
+        let name = match path.file_name().and_then(|x| x.to_str()) {
+            Some(name) => name,
+            None => {
+                rejected.push(format!("{} has an invalid name", path.display()));
+                continue;
+            }
+        };
         println!("{name:?}");
-        let mut img = image::open(path).unwrap().to_rgba8();
+        let mut img = match image::open(path) {
+            Ok(img) => img.to_rgba8(),
+            Err(e) => {
+                rejected.push(format!("{name}: {e}"));
+                continue;
+            }
+        };
         fix_translucent(&mut img);
-        let img = decode_srgb(&img);
-        assert_eq!(
-            img.dimensions().0,
-            img.dimensions().1,
-            "Image was not square!"
-        );
-        assert!(img.dimensions().0 >= 384, "Image dimensions are too small!");
-        let img = resize(&img, 192, 192);
+        let (w, h) = img.dimensions();
+        // Check the original, as letterboxing a thin sliver would make it look large enough.
+        if w.min(h) < options.min_size {
+            rejected.push(format!(
+                "{name} is {w}x{h} which is smaller than {} pixels",
+                options.min_size
+            ));
+            continue;
+        }
+        let img = match fit_square(decode_srgb(&img), options.fit) {
+            Some(img) => img,
+            None => {
+                rejected.push(format!("{name} is {w}x{h} which is not square"));
+                continue;
+            }
+        };
+        let img = resize(&img, options.size, options.size, options.filter);
         let img = encode_srgb(&img);
-        img.save(format!("work/shrunk/Block {name}")).unwrap();
+        let out = options.output.join(format!("{}{name}", options.prefix));
+        img.save(&out).map_err(Error::image(out.display()))?;
+    }
+    for reason in &rejected {
+        println!("WARNING: Rejected {reason}");
     }
+    println!("Rejected {} images.", rejected.len());
+    Ok(rejected.is_empty())
 }
-fn main() {
-    println!("Welcome to the FTB tilesheet program!");
-    if !Path::new("ftb.json").is_file() {
-        println!("Failed to locate ftb.json.");
-        println!("Please modify the template ftb.json that was created.");
-        println!("Make sure you use a bot account!");
-        let mut file = File::create("ftb.json").unwrap();
-        file.write_all(
-            r#"{
+fn cli() -> Command<'static> {
+    let name = || Arg::new("mod").required(true).help("Mod abbreviation");
+    let source = || {
+        [
+            Arg::new("from")
+                .long("from")
+                .value_name("ARCHIVE")
+                .value_parser(value_parser!(PathBuf))
+                .help("Read the tiles from a mod jar or resource pack zip"),
+            Arg::new("namespace")
+                .long("namespace")
+                .value_name("MODID")
+                .action(ArgAction::Append)
+                .requires("from")
+                .help("Only read textures from this asset namespace"),
+            Arg::new("textures")
+                .long("textures")
+                .value_name("FOLDERS")
+                .requires("from")
+                .help("Comma separated texture folders to read [default: item,block,items,blocks]"),
+        ]
+    };
+    let names = || {
+        Arg::new("names")
+            .long("names")
+            .action(ArgAction::SetTrue)
+            .help("Name tiles after their display names in the language files")
+    };
+    let placement = || {
+        [
+            Arg::new("layout")
+                .long("layout")
+                .value_name("LAYOUT")
+                .help("Order to fill new cells in: shell or rows:<width> [default: shell]"),
+            Arg::new("group")
+                .long("group")
+                .value_name("GROUP")
+                .help("Keep new tiles together by name prefix or folder: none, prefix or folder"),
+            Arg::new("max-layer-size")
+                .long("max-layer-size")
+                .value_name("CELLS")
+                .value_parser(value_parser!(u32).range(1..))
+                .help("Most cells along a side of a layer before starting a new one [default: 64]"),
+        ]
+    };
+    let dry_run = || {
+        Arg::new("dry-run")
+            .long("dry-run")
+            .action(ArgAction::SetTrue)
+            .help("Write a report of the changes instead of making them")
+    };
+    let yes = || {
+        Arg::new("yes")
+            .long("yes")
+            .short('y')
+            .action(ArgAction::SetTrue)
+            .help("Accept the pending changes without prompting")
+    };
+    let allow_upload_warnings = || {
+        Arg::new("allow-upload-warnings")
+            .long("allow-upload-warnings")
+            .action(ArgAction::SetTrue)
+            .help("Finish uploads that the wiki answered with warnings")
+    };
+    let frame = || {
+        Arg::new("frame")
+            .long("frame")
+            .value_name("FRAME")
+            .help("Frame of animated textures to use: first, blend or an index")
+    };
+    let strips = || {
+        Arg::new("strips")
+            .long("strips")
+            .action(ArgAction::SetTrue)
+            .help("Cut tall textures without a .png.mcmeta file into square frames")
+    };
+    let fit = || {
+        Arg::new("fit")
+            .long("fit")
+            .value_name("FIT")
+            .help("How to fit images that are not square: letterbox, crop or reject")
+    };
+    let filter = || {
+        Arg::new("filter")
+            .long("filter")
+            .value_name("FILTER")
+            .help("Resampling filter to use: box, nearest, mitchell or lanczos3")
+    };
+    Command::new("ftb")
+        .about("Manages tilesheets on the FTB wiki")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("update")
+                .about("Updates a mod's tilesheet from the tiles in tilesheets/<mod>")
+                .arg(name())
+                .arg(dry_run())
+                .arg(yes())
+                .arg(
+                    Arg::new("sizes")
+                        .long("sizes")
+                        .value_name("SIZES")
+                        .help("Comma separated sizes to use if the tilesheet does not exist"),
+                )
+                .arg(allow_upload_warnings())
+                .arg(
+                    Arg::new("max-deletions")
+                        .long("max-deletions")
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("Abort if more than N tiles would be deleted"),
+                )
+                .arg(
+                    Arg::new("force-deletions")
+                        .long("force-deletions")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete tiles even if pages on the wiki still use them"),
+                )
+                .arg(frame())
+                .arg(strips())
+                .arg(fit())
+                .arg(filter())
+                .args(placement())
+                .args(source())
+                .arg(names()),
+        )
+        .subcommand(
+            Command::new("resume")
+                .about("Finishes an update that was interrupted while changing the wiki")
+                .arg(name())
+                .arg(yes().help("Fail instead of prompting"))
+                .arg(
+                    Arg::new("discard")
+                        .long("discard")
+                        .action(ArgAction::SetTrue)
+                        .help("Drop the unfinished update instead of finishing it"),
+                )
+                .arg(allow_upload_warnings()),
+        )
+        .subcommand(
+            Command::new("repack")
+                .about("Moves a mod's tiles into a compact layout and rebuilds its tilesheet")
+                .arg(name())
+                .arg(dry_run())
+                .arg(yes().help("Accept the new layout without prompting"))
+                .args(placement())
+                .arg(allow_upload_warnings()),
+        )
+        .subcommand(
+            Command::new("sizes")
+                .about("Adds sizes to a mod's tilesheet or retires them")
+                .arg(name())
+                .arg(
+                    Arg::new("add")
+                        .long("add")
+                        .value_name("SIZES")
+                        .help("Comma separated sizes to render every tile at"),
+                )
+                .arg(
+                    Arg::new("remove")
+                        .long("remove")
+                        .value_name("SIZES")
+                        .help("Comma separated sizes to retire"),
+                )
+                .group(
+                    ArgGroup::new("change")
+                        .args(&["add", "remove"])
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(dry_run())
+                .arg(yes().help("Accept the new sizes without prompting"))
+                .arg(allow_upload_warnings())
+                .arg(frame())
+                .arg(strips())
+                .arg(fit())
+                .arg(filter())
+                .args(source())
+                .arg(names()),
+        )
+        .subcommand(
+            Command::new("extract")
+                .about("Cuts the registered tiles out of a mod's tilesheet into tilesheets/<mod>")
+                .arg(name())
+                .arg(
+                    Arg::new("size")
+                        .long("size")
+                        .value_name("SIZE")
+                        .value_parser(value_parser!(u32))
+                        .conflicts_with("all-sizes")
+                        .help("Extract from this size instead of the largest one"),
+                )
+                .arg(
+                    Arg::new("all-sizes")
+                        .long("all-sizes")
+                        .action(ArgAction::SetTrue)
+                        .help("Extract every size into tilesheets/<mod> <size>"),
+                )
+                .arg(
+                    Arg::new("overwrite")
+                        .long("overwrite")
+                        .action(ArgAction::SetTrue)
+                        .help("Replace tiles that already exist locally"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Checks a mod's registered tiles against its tilesheet images")
+                .arg(name()),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Lists the tiles an update would add, rename and leave missing")
+                .arg(name())
+                .args(source())
+                .arg(names()),
+        )
+        .subcommand(
+            Command::new("translate")
+                .about("Sets the translated names of a mod's tiles from its language files")
+                .arg(name())
+                .arg(dry_run().help("Only write the translations to tilesheets/translations.txt"))
+                .arg(yes().help("Set the translations without prompting"))
+                .args(source()),
+        )
+        .subcommand(
+            Command::new("duplicates")
+                .about("Groups a mod's tiles that are identical or look alike")
+                .arg(name())
+                .args(source())
+                .arg(names()),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Lists the tilesheets on the wiki, or the tiles of one mod")
+                .arg(Arg::new("mod").help("Mod abbreviation")),
+        )
+        .subcommand(
+            Command::new("shrink")
+                .about("Shrinks a directory of block renders to a fixed size")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .value_name("DIR")
+                        .value_parser(value_parser!(PathBuf))
+                        .default_value("work/shrink")
+                        .help("Directory of images to shrink"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .value_name("DIR")
+                        .value_parser(value_parser!(PathBuf))
+                        .default_value("work/shrunk")
+                        .help("Directory to save the shrunk images in"),
+                )
+                .arg(
+                    Arg::new("size")
+                        .long("size")
+                        .value_name("SIZE")
+                        .value_parser(value_parser!(u32))
+                        .default_value("192")
+                        .help("Size of the shrunk images"),
+                )
+                .arg(
+                    Arg::new("min-size")
+                        .long("min-size")
+                        .value_name("SIZE")
+                        .value_parser(value_parser!(u32))
+                        .default_value("384")
+                        .help("Reject images smaller than this"),
+                )
+                .arg(
+                    Arg::new("prefix")
+                        .long("prefix")
+                        .value_name("PREFIX")
+                        .default_value("Block ")
+                        .help("Prefix for the names of the shrunk images"),
+                )
+                .arg(fit().default_value("reject"))
+                .arg(filter().default_value("box")),
+        )
+}
+/// Makes sure ftb.json exists, creating a template for the user to fill in if it does not.
+fn check_config() -> bool {
+    if Path::new("ftb.json").is_file() {
+        return true;
+    }
+    println!("Failed to locate ftb.json.");
+    println!("Please modify the template ftb.json that was created.");
+    println!("Make sure you use a bot account!");
+    let mut file = File::create("ftb.json").unwrap();
+    file.write_all(
+        r#"{
     "useragent": "ftb-rs",
     "username": "insert bot username here",
     "password": "insert bot password here",
     "baseapi": "https://ftb.fandom.com/api.php"
 }
 "#
-            .as_bytes(),
-        )
-        .unwrap();
-        return;
-    }
-    let mut args = args();
-    args.next();
-    let abbrv = match args.next() {
-        Some(x) => x,
-        None => {
-            println!("Enter mod abbreviation:");
-            let mut abbrv = String::new();
-            stdin().read_line(&mut abbrv).unwrap();
-            abbrv.trim().to_owned()
-        }
+        .as_bytes(),
+    )
+    .unwrap();
+    false
+}
+fn source(args: &ArgMatches) -> tilesheets::Source {
+    let path = match args.get_one::<PathBuf>("from") {
+        Some(path) => path.clone(),
+        None => return tilesheets::Source::Directory,
     };
-    tilesheets::update_tilesheet(&abbrv);
+    let mut filter = tilesheets::TextureFilter::default();
+    if let Some(namespaces) = args.get_many::<String>("namespace") {
+        filter.namespaces = namespaces.cloned().collect();
+    }
+    if let Some(folders) = args.get_one::<String>("textures") {
+        filter.folders = folders.split(',').map(|x| x.trim().to_owned()).collect();
+    }
+    tilesheets::Source::Archive { path, filter }
+}
+fn placement(args: &ArgMatches) -> Result<tilesheets::Placement> {
+    let mut placement = tilesheets::Placement::default();
+    if let Some(layout) = parse_arg(args, "layout", tilesheets::Layout::parse)? {
+        placement.layout = layout;
+    }
+    if let Some(grouping) = parse_arg(args, "group", tilesheets::Grouping::parse)? {
+        placement.grouping = grouping;
+    }
+    if let Some(&max_size) = args.get_one::<u32>("max-layer-size") {
+        placement.max_size = max_size;
+    }
+    Ok(placement)
+}
+/// Parses an optional argument with one of the `parse` functions.
+fn parse_arg<T>(args: &ArgMatches, id: &str, parse: fn(&str) -> Option<T>) -> Result<Option<T>> {
+    match args.get_one::<String>(id) {
+        Some(value) => match parse(value) {
+            Some(value) => Ok(Some(value)),
+            None => Err(Error::Invalid(format!("invalid {id} {value:?}"))),
+        },
+        None => Ok(None),
+    }
+}
+/// Runs a command, returning whether it succeeded.
+fn run(command: &str, args: &ArgMatches) -> Result<bool> {
+    let name = || args.get_one::<String>("mod").unwrap();
+    let base = Path::new(tilesheets::BASE_PATH);
+    match command {
+        "update" => {
+            let options = tilesheets::Options {
+                dry_run: args.get_flag("dry-run"),
+                yes: args.get_flag("yes"),
+                sizes: parse_arg(args, "sizes", tilesheets::parse_sizes)?,
+                allow_upload_warnings: args.get_flag("allow-upload-warnings"),
+                max_deletions: args.get_one::<usize>("max-deletions").copied(),
+                force_deletions: args.get_flag("force-deletions"),
+                source: source(args),
+                names: args.get_flag("names"),
+                frame: parse_arg(args, "frame", tilesheets::Frame::parse)?.unwrap_or_default(),
+                strips: args.get_flag("strips"),
+                fit: parse_arg(args, "fit", Fit::parse)?.unwrap_or_default(),
+                filter: parse_arg(args, "filter", Filter::parse)?,
+                placement: placement(args)?,
+            };
+            tilesheets::update_tilesheet(tilesheets::login()?, base, name(), options)?;
+        }
+        "resume" if args.get_flag("discard") => tilesheets::discard_update(base, name())?,
+        "resume" => {
+            let options = tilesheets::Options {
+                yes: args.get_flag("yes"),
+                allow_upload_warnings: args.get_flag("allow-upload-warnings"),
+                ..Default::default()
+            };
+            tilesheets::resume_update(tilesheets::login()?, base, name(), options)?;
+        }
+        "repack" => {
+            let options = tilesheets::Options {
+                dry_run: args.get_flag("dry-run"),
+                yes: args.get_flag("yes"),
+                allow_upload_warnings: args.get_flag("allow-upload-warnings"),
+                placement: placement(args)?,
+                ..Default::default()
+            };
+            tilesheets::repack_tilesheet(tilesheets::login()?, base, name(), options)?;
+        }
+        "sizes" => {
+            let options = tilesheets::Options {
+                dry_run: args.get_flag("dry-run"),
+                yes: args.get_flag("yes"),
+                allow_upload_warnings: args.get_flag("allow-upload-warnings"),
+                source: source(args),
+                names: args.get_flag("names"),
+                frame: parse_arg(args, "frame", tilesheets::Frame::parse)?.unwrap_or_default(),
+                strips: args.get_flag("strips"),
+                fit: parse_arg(args, "fit", Fit::parse)?.unwrap_or_default(),
+                filter: parse_arg(args, "filter", Filter::parse)?,
+                ..Default::default()
+            };
+            let add = parse_arg(args, "add", tilesheets::parse_sizes)?.unwrap_or_default();
+            let remove = parse_arg(args, "remove", tilesheets::parse_sizes)?.unwrap_or_default();
+            tilesheets::change_sizes(tilesheets::login()?, base, name(), &add, &remove, options)?;
+        }
+        "extract" => {
+            let size = if args.get_flag("all-sizes") {
+                tilesheets::ExtractSize::All
+            } else if let Some(&size) = args.get_one::<u32>("size") {
+                tilesheets::ExtractSize::Size(size)
+            } else {
+                tilesheets::ExtractSize::Largest
+            };
+            let overwrite = args.get_flag("overwrite");
+            tilesheets::extract_tiles(tilesheets::login()?, base, name(), size, overwrite)?;
+        }
+        "verify" => return tilesheets::verify_tilesheet(tilesheets::login()?, base, name()),
+        "diff" => {
+            let options = tilesheets::Options {
+                source: source(args),
+                names: args.get_flag("names"),
+                ..tilesheets::Options::default()
+            };
+            tilesheets::diff_tilesheet(tilesheets::login()?, base, name(), options)?;
+        }
+        "translate" => {
+            let options = tilesheets::Options {
+                dry_run: args.get_flag("dry-run"),
+                yes: args.get_flag("yes"),
+                source: source(args),
+                ..tilesheets::Options::default()
+            };
+            tilesheets::sync_translations(tilesheets::login()?, base, name(), options)?;
+        }
+        "duplicates" => {
+            let options = tilesheets::Options {
+                source: source(args),
+                names: args.get_flag("names"),
+                ..tilesheets::Options::default()
+            };
+            tilesheets::find_duplicates(tilesheets::login()?, base, name(), options)?;
+        }
+        "list" => match args.get_one::<String>("mod") {
+            Some(name) => tilesheets::list_tiles(tilesheets::login()?, name)?,
+            None => tilesheets::list_tilesheets(tilesheets::login()?)?,
+        },
+        "shrink" => {
+            let options = Shrink {
+                input: args.get_one::<PathBuf>("input").unwrap().clone(),
+                output: args.get_one::<PathBuf>("output").unwrap().clone(),
+                size: *args.get_one::<u32>("size").unwrap(),
+                min_size: *args.get_one::<u32>("min-size").unwrap(),
+                prefix: args.get_one::<String>("prefix").unwrap().clone(),
+                filter: parse_arg(args, "filter", Filter::parse)?.unwrap_or_default(),
+                fit: parse_arg(args, "fit", Fit::parse)?.unwrap_or_default(),
+            };
+            return shrink(&options);
+        }
+        _ => unreachable!(),
+    }
+    Ok(true)
+}
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+    println!("Welcome to the FTB tilesheet program!");
+    let (command, args) = matches.subcommand().unwrap();
+    if command != "shrink" && !check_config() {
+        return ExitCode::FAILURE;
+    }
+    match run(command, args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("ERROR: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 4] = [
+        Filter::Box,
+        Filter::Nearest,
+        Filter::Mitchell,
+        Filter::Lanczos3,
+    ];
+
+    #[test]
+    fn taps_are_normalized_and_in_range() {
+        for filter in FILTERS {
+            for (src, dst) in [(32, 16), (16, 32), (24, 16), (16, 24), (7, 3), (5, 5)] {
+                let taps = filter.taps(src, dst);
+                assert_eq!(taps.len(), dst as usize);
+                for pixel in taps {
+                    assert!(pixel.iter().all(|&(j, _)| j < src));
+                    let total = pixel.iter().map(|&(_, weight)| weight).sum::<f32>();
+                    assert!((total - 1.).abs() < 1e-4, "{filter:?} {src}->{dst}");
+                }
+            }
+        }
+    }
+    #[test]
+    fn nearest_picks_centers() {
+        let taps = Filter::Nearest.taps(4, 2);
+        assert_eq!(taps, vec![vec![(1, 1.)], vec![(3, 1.)]]);
+        let taps = Filter::Nearest.taps(2, 4);
+        assert_eq!(
+            taps,
+            vec![vec![(0, 1.)], vec![(0, 1.)], vec![(1, 1.)], vec![(1, 1.)]]
+        );
+    }
+    #[test]
+    fn resize_keeps_uniform_images_uniform() {
+        let color = Rgba([0.25, 0.5, 0.125, 0.5]);
+        let img = FloatImage::from_pixel(24, 24, color);
+        for filter in FILTERS {
+            for size in [8, 16, 32, 48] {
+                let resized = resize(&img, size, size, filter);
+                assert_eq!(resized.dimensions(), (size, size));
+                for p in resized.pixels() {
+                    for (a, b) in p.0.iter().zip(color.0) {
+                        assert!((a - b).abs() < 1e-4, "{filter:?} {size}");
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn pixel_grid_finds_enlargement() {
+        let small = FloatImage::from_fn(4, 4, |x, y| Rgba([(x * 4 + y) as f32 / 16., 0., 0., 1.]));
+        assert_eq!(pixel_grid(&small), 1);
+        let enlarged = resize(&small, 8, 8, Filter::Nearest);
+        assert_eq!(pixel_grid(&enlarged), 2);
+        let enlarged = resize(&small, 12, 12, Filter::Nearest);
+        assert_eq!(pixel_grid(&enlarged), 3);
+        let uniform = FloatImage::from_pixel(16, 16, Rgba([1., 1., 1., 1.]));
+        assert_eq!(pixel_grid(&uniform), 16);
+    }
 }
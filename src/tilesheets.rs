@@ -1,43 +1,74 @@
-use crate::{decode_srgb, encode_srgb, fix_translucent, resize, FloatImage};
-use image::{self, ImageBuffer, RgbaImage};
-use lazy_static::lazy_static;
-use mediawiki::{tilesheet::Tilesheet, Error as MwError, Mediawiki, Upload};
+use crate::{
+    encode_srgb,
+    error::{Error, Result},
+    fit_square, fix_translucent, pixel_grid, resize, Filter, Fit, FloatImage,
+};
+pub use animation::Frame;
+use backend::{Backend, UploadResult};
+use image::{self, imageops, ImageBuffer, RgbaImage};
+use journal::Journal;
+use mediawiki::Mediawiki;
+use naming::Names;
+pub use placement::{Grouping, Layout, Placement};
+use preview::Preview;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use source::TileImage;
+pub use source::{Source, TextureFilter};
 use std::{
     borrow::ToOwned,
     cmp::max,
     collections::{HashMap, HashSet},
-    fs::File,
-    io::{stdin, BufRead, BufReader, BufWriter, Read, Write},
-    path::PathBuf,
-    process::exit,
+    fs::{self, create_dir_all, File},
+    io::{stdin, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
     thread::spawn,
 };
-use walkdir::WalkDir;
 
-const MAX_SIZE: u32 = 64;
-lazy_static! {
-    static ref BASE_PATH: PathBuf = "tilesheets".into();
-}
+mod animation;
+mod backend;
+mod journal;
+mod naming;
+mod placement;
+mod preview;
+mod repack;
+mod similar;
+mod sizes;
+mod source;
+mod translate;
+mod verify;
+
+/// How many tiles are added or deleted in one request.
+const CHUNK_SIZE: usize = 50;
+/// Textures up to this size are treated as pixel art even if they are not enlarged.
+const PIXEL_ART_SIZE: u32 = 32;
+/// The folder that holds the tiles of each mod and the reports.
+pub const BASE_PATH: &str = "tilesheets";
 
 struct Sheet {
     size: u32,
     layers: Vec<RgbaImage>,
+    /// Whether each layer differs from the one imported from the wiki.
+    changed: Vec<bool>,
 }
 impl Sheet {
     fn new(size: u32) -> Sheet {
         Sheet {
             size,
             layers: Vec::new(),
+            changed: Vec::new(),
         }
     }
-    fn load_layer(&mut self, data: &[u8]) {
-        let layer = image::load_from_memory(data).unwrap();
+    fn load_layer(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let layer = image::load_from_memory(data).map_err(Error::image(name))?;
         self.layers.push(layer.to_rgba8());
+        self.changed.push(false);
+        Ok(())
     }
     fn add_layer(&mut self) {
         let layer = ImageBuffer::new(self.size, self.size);
         self.layers.push(layer);
+        self.changed.push(true);
     }
     fn grow(&mut self, w: u32, h: u32, z: u32) {
         let mut new_layer = ImageBuffer::new(w, h);
@@ -46,13 +77,25 @@ impl Sheet {
             new_layer.put_pixel(x, y, pix);
         }
         *old_layer = new_layer;
+        self.changed[z as usize] = true;
     }
-    fn insert(&mut self, TilePos { x, y, z }: TilePos, img: &FloatImage) {
+    /// Resizes a tile into a cell. Pixel art is enlarged with nearest neighbour instead when the
+    /// size is a whole multiple of it.
+    fn insert(&mut self, pos: TilePos, img: &FloatImage, filter: Filter, pixel_art: bool) {
         let (width, height) = img.dimensions();
         assert!(width == height);
-        let img = resize(img, self.size, self.size);
-        let img = encode_srgb(&img);
-        if z as usize == self.layers.len() {
+        let filter = if pixel_art && self.size.is_multiple_of(width) {
+            Filter::Nearest
+        } else {
+            filter
+        };
+        let img = resize(img, self.size, self.size, filter);
+        self.place(pos, &encode_srgb(&img));
+    }
+    /// Copies a tile that is already the right size into a cell, adding or growing the layer if
+    /// needed.
+    fn place(&mut self, TilePos { x, y, z }: TilePos, img: &RgbaImage) {
+        while z as usize >= self.layers.len() {
             self.add_layer();
         }
         let (w, h) = self.layers[z as usize].dimensions();
@@ -63,9 +106,33 @@ impl Sheet {
         }
         let (x, y) = (x * self.size, y * self.size);
         let layer = &mut self.layers[z as usize];
+        let mut changed = false;
         for (xx, yy, &pix) in img.enumerate_pixels() {
-            layer.put_pixel(x + xx, y + yy, pix);
+            if *layer.get_pixel(x + xx, y + yy) != pix {
+                layer.put_pixel(x + xx, y + yy, pix);
+                changed = true;
+            }
+        }
+        if changed {
+            self.changed[z as usize] = true;
+        }
+    }
+    /// The layers that need to be uploaded, along with their z index.
+    fn changed_layers(&self) -> impl Iterator<Item = (usize, &RgbaImage)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .filter(move |&(z, _)| self.changed[z])
+    }
+    /// Cuts the cell at the given position out of its layer.
+    fn tile(&self, TilePos { x, y, z }: TilePos) -> Option<RgbaImage> {
+        let layer = self.layers.get(z as usize)?;
+        let (w, h) = layer.dimensions();
+        if (x + 1) * self.size > w || (y + 1) * self.size > h {
+            return None;
         }
+        let (x, y) = (x * self.size, y * self.size);
+        Some(imageops::crop_imm(layer, x, y, self.size, self.size).to_image())
     }
 }
 #[derive(Debug)]
@@ -73,322 +140,618 @@ struct Tile {
     pos: TilePos,
     id: Option<u64>,
 }
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
-struct TilePos {
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct TilePos {
     x: u32,
     y: u32,
     z: u32,
 }
-struct TilesheetManager {
-    mw: Mediawiki,
+struct TilesheetManager<B: Backend> {
+    wiki: B,
+    /// The folder with the mod's tiles, where reports and tilesheet images are written.
+    base: PathBuf,
     name: String,
     tiles: HashMap<String, Tile>,
     entries: HashMap<TilePos, String>,
+    /// Registered tiles that share a position with another tile.
+    overlaps: Vec<(TilePos, String, String)>,
     renames: HashMap<String, String>,
+    /// Renames of registered tiles, in the order they are listed.
+    tile_renames: Vec<(String, String)>,
+    /// Resampling filters for individual tiles, by file name.
+    filters: HashMap<String, Filter>,
+    /// Display names from the mod's language files, when naming tiles after them.
+    names: Option<Names>,
     added: Vec<String>,
+    renamed: Vec<(u64, String, String)>,
+    /// Tiles moved to a new position, by id.
+    moved: Vec<(u64, String, TilePos)>,
     missing: HashSet<String>,
-    deleted: Vec<u64>,
+    deleted: Vec<(u64, String)>,
+    /// Tiles being deleted that pages still use, along with those pages.
+    in_use: Vec<(String, Vec<String>)>,
     tilesheets: Vec<Sheet>,
-    next: (u32, u32, u32),
+    /// Tilesheet images left without tiles, which are deleted from the wiki.
+    emptied: Vec<String>,
+    /// The sizes the tilesheet should have, when they changed.
+    new_sizes: Option<Vec<u32>>,
+    /// The first position in fill order that may still be free.
+    next: u64,
+    options: Options,
+    preview: Preview,
+}
+/// Settings controlling how an update runs and what it may do without asking.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Skip every step that modifies the wiki and write a report instead.
+    pub dry_run: bool,
+    /// Accept the pending changes without prompting.
+    pub yes: bool,
+    /// Sizes to use when the tilesheet does not exist yet.
+    pub sizes: Option<Vec<u32>>,
+    /// Finish uploads that the wiki answered with warnings without prompting.
+    pub allow_upload_warnings: bool,
+    /// Abort if more than this many tiles would be deleted.
+    pub max_deletions: Option<usize>,
+    /// Delete tiles even if pages on the wiki still use them.
+    pub force_deletions: bool,
+    /// Where to find the images for the mod's tiles.
+    pub source: Source,
+    /// Name tiles after the display names in the mod's language files.
+    pub names: bool,
+    /// Which frame of an animated texture to use.
+    pub frame: Frame,
+    /// Cut textures without an animation sidecar into frames when they are a vertical strip of
+    /// square frames.
+    pub strips: bool,
+    /// How to fit textures that are not square.
+    pub fit: Fit,
+    /// The resampling filter for tiles that filters.txt does not mention.
+    pub filter: Option<Filter>,
+    /// How new tiles are assigned positions.
+    pub placement: Placement,
 }
-impl TilesheetManager {
-    fn new(name: &str) -> TilesheetManager {
+fn read_line() -> Result<String> {
+    let mut line = String::new();
+    stdin().read_line(&mut line).map_err(Error::io("<stdin>"))?;
+    Ok(line)
+}
+fn prompt_continue() -> Result<()> {
+    if read_line()?.trim().to_lowercase() != "continue" {
+        return Err(Error::Aborted("\"continue\" was not entered".into()));
+    }
+    Ok(())
+}
+impl<B: Backend> TilesheetManager<B> {
+    fn new(wiki: B, base: &Path, name: &str, mut options: Options) -> Result<TilesheetManager<B>> {
         println!("Starting up tilesheet manager.");
-        TilesheetManager {
-            mw: Mediawiki::login_path("ftb.json").unwrap(),
+        let (renames, tile_renames) = load_renames(base, name)?;
+        let (default_filter, filters) = load_filters(base, name)?;
+        options.filter = options.filter.or(default_filter);
+        let names = if options.names {
+            let names = options.source.names(base, name)?;
+            println!("Loaded {} translations.", names.translations());
+            Some(names)
+        } else {
+            None
+        };
+        Ok(TilesheetManager {
+            wiki,
+            base: base.to_owned(),
             name: name.to_owned(),
             tiles: HashMap::new(),
             entries: HashMap::new(),
-            renames: load_renames(name),
+            overlaps: Vec::new(),
+            renames,
+            tile_renames,
+            filters,
+            names,
             added: Vec::new(),
+            renamed: Vec::new(),
+            moved: Vec::new(),
             missing: HashSet::new(),
             deleted: Vec::new(),
+            in_use: Vec::new(),
             tilesheets: Vec::new(),
-            next: (0, 0, 0),
+            emptied: Vec::new(),
+            new_sizes: None,
+            next: 0,
+            options,
+            preview: Preview::default(),
+        })
+    }
+    fn import_tilesheets(&mut self) -> Result<()> {
+        if !self.import_existing_tilesheets()? {
+            self.create_tilesheets()?;
         }
+        Ok(())
     }
-    fn import_tilesheets(&mut self) {
+    /// Downloads the layers of the existing tilesheet, returning false if there is none.
+    fn import_existing_tilesheets(&mut self) -> Result<bool> {
         println!("Checking for existing tilesheet.");
-        let sheet = self.mw.query_sheets().into_iter().find(|x| {
-            x.as_ref()
-                .ok()
-                .and_then(|x| x.get("mod"))
-                .and_then(|x| x.as_str())
-                .map_or(false, |x| x == self.name)
-        });
-        if let Some(Ok(sheet)) = sheet {
-            let sizes: Vec<u64> = sheet["sizes"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .map(|x| x.as_u64().unwrap())
-                .collect();
-            println!("Existing tilesheet sizes: {sizes:?}");
-            println!("Importing existing tilesheet images.");
-            for size in sizes {
-                let mut sheet = Sheet::new(size as u32);
-                for z in 0.. {
-                    if let Some(data) = self
-                        .mw
-                        .download_file(&format!("Tilesheet {} {} {}.png", self.name, size, z))
-                        .unwrap()
-                    {
-                        sheet.load_layer(&data);
-                    } else {
-                        if z == 0 {
-                            println!("WARNING: No tilesheet image found for size {size}!");
-                        }
-                        break;
+        let (mut found, mut malformed) = (None, Vec::new());
+        for sheet in self.wiki.query_sheets() {
+            match sheet {
+                Ok(sheet) if sheet.name == self.name => found = Some(sheet),
+                Ok(_) => {}
+                // A malformed record of another mod should not stop this one from updating.
+                Err(Error::Response(msg)) => malformed.push(msg),
+                Err(e) => return Err(e),
+            }
+        }
+        let sheet = match found {
+            Some(sheet) => sheet,
+            // The malformed record could be this mod's, so creating a new tilesheet is not safe.
+            None if !malformed.is_empty() => {
+                return Err(Error::Response(format!(
+                    "no tilesheet found for {:?} and some records were malformed: {}",
+                    self.name,
+                    malformed.join("; ")
+                )))
+            }
+            None => return Ok(false),
+        };
+        for msg in malformed {
+            println!("WARNING: Skipping malformed tilesheet record: {msg}");
+        }
+        let sizes = sheet.sizes;
+        println!("Existing tilesheet sizes: {sizes:?}");
+        println!("Importing existing tilesheet images.");
+        for size in sizes {
+            let mut sheet = Sheet::new(size);
+            for z in 0.. {
+                let name = format!("Tilesheet {} {} {}.png", self.name, size, z);
+                if let Some(data) = self.wiki.download_file(&name)? {
+                    sheet.load_layer(&name, &data)?;
+                } else {
+                    if z == 0 {
+                        println!("WARNING: No tilesheet image found for size {size}!");
                     }
+                    break;
                 }
-                self.tilesheets.push(sheet);
             }
+            self.tilesheets.push(sheet);
+        }
+        Ok(true)
+    }
+    fn create_tilesheets(&mut self) -> Result<()> {
+        let sizes = if let Some(sizes) = &self.options.sizes {
+            println!("No tilesheet found. Using sizes {sizes:?}");
+            sizes.clone()
+        } else if self.options.yes {
+            return Err(Error::Aborted(
+                "no tilesheet found and no sizes were specified with --sizes".into(),
+            ));
         } else {
             println!("No tilesheet found. Please specify desired sizes separated by commas:");
-            let mut sizes = String::new();
-            stdin().read_line(&mut sizes).unwrap();
-            let sizes = sizes.split(',').map(str::trim).collect::<Vec<_>>();
-            for size in &sizes {
-                self.tilesheets.push(Sheet::new(size.parse().unwrap()));
-            }
-            let token = self.mw.get_token().unwrap();
-            self.mw
-                .create_sheet(
-                    &token,
-                    &self.name,
-                    &sizes.join("|"),
-                    "Tilesheet created by ftb-rs",
-                )
-                .unwrap();
+            let sizes = read_line()?;
+            parse_sizes(&sizes)
+                .ok_or_else(|| Error::Invalid(format!("invalid sizes {:?}", sizes.trim())))?
+        };
+        for &size in &sizes {
+            self.tilesheets.push(Sheet::new(size));
+        }
+        if self.options.dry_run {
+            println!("Dry run: not creating tilesheet.");
+            return Ok(());
         }
+        self.wiki.create_sheet(&self.name, &sizes)
     }
-    fn import_tiles(&mut self) {
+    /// Loads the registered tiles, failing if any of them cannot be read, as a tile that was
+    /// skipped would be added again at another position.
+    fn import_tiles(&mut self) -> Result<()> {
         println!("Importing tiles.");
-        for tile in self.mw.query_tiles(Some(&*self.name)) {
-            let tile = match tile {
-                Ok(tile) => tile,
-                Err(e) => {
-                    println!("WARNING: Error while querying tiles {e:?}");
-                    continue;
+        for tile in self.wiki.query_tiles(&self.name) {
+            let tile = tile?;
+            let pos = tile.pos;
+            self.tiles.insert(
+                tile.name.clone(),
+                Tile {
+                    pos,
+                    id: Some(tile.id),
+                },
+            );
+            if let Some(other) = self.entries.insert(pos, tile.name.clone()) {
+                self.overlaps.push((pos, other, tile.name.clone()));
+            }
+            self.missing.insert(tile.name);
+        }
+        Ok(())
+    }
+    fn record_renames(&mut self) -> Result<()> {
+        for (old, new) in &self.tile_renames {
+            if self.tiles.contains_key(new) {
+                if self.tiles.contains_key(old) {
+                    return Err(Error::Invalid(format!(
+                        "cannot rename {old:?} to {new:?} as both tiles exist"
+                    )));
                 }
-            };
-            let x = tile["x"].as_u64().unwrap() as u32;
-            let y = tile["y"].as_u64().unwrap() as u32;
-            let z = tile["z"].as_u64().unwrap() as u32;
-            let id = tile["id"].as_u64().unwrap();
-            let name = tile["name"].as_str().unwrap();
-            let pos = TilePos { x, y, z };
-            self.tiles
-                .insert(name.to_owned(), Tile { pos, id: Some(id) });
-            self.entries.insert(pos, name.to_owned());
-            self.missing.insert(name.to_owned());
-        }
-    }
-    fn check_changes(&mut self) {
-        println!("Checking tiles.");
-        let path = BASE_PATH.join(&self.name);
-        for entry in WalkDir::new(&path) {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if !path.is_file() {
                 continue;
             }
-            if path.extension().and_then(|x| x.to_str()) != Some("png") {
-                continue;
+            let tile = match self.tiles.remove(old) {
+                Some(tile) => tile,
+                None => {
+                    println!("WARNING: Requested to rename tile that doesn't exist {old:?}");
+                    continue;
+                }
+            };
+            check_name(new)?;
+            self.entries.insert(tile.pos, new.clone());
+            self.missing.remove(old);
+            self.missing.insert(new.clone());
+            // A tile renamed more than once only needs its last name.
+            let id = tile.id.unwrap();
+            match self.renamed.iter_mut().find(|(other, _, _)| *other == id) {
+                Some((_, _, name)) => *name = new.clone(),
+                None => self.renamed.push((id, old.clone(), new.clone())),
             }
-            let name = path.file_stem().unwrap().to_str().unwrap();
-            let name = match self.renames.get(name) {
-                Some(name) => {
+            self.tiles.insert(new.clone(), tile);
+        }
+        Ok(())
+    }
+    /// Finds the tile images, returning each one's wiki name along with the image.
+    /// Entries in renames.txt take precedence over display names from the language files.
+    fn local_tiles(&self) -> Result<Vec<(String, TileImage)>> {
+        let mut tiles: Vec<(String, TileImage)> = Vec::new();
+        let mut unnamed = Vec::new();
+        for image in self.options.source.images(&self.base, &self.name)? {
+            let name = match (self.renames.get(&image.stem), &self.names) {
+                (Some(name), _) => {
                     if name.is_empty() {
                         continue;
                     }
                     name.clone()
                 }
-                None => name.to_owned(),
+                (None, Some(names)) => match names.name(&image) {
+                    Some(name)
+                        if check_name(name).is_ok()
+                            && !tiles.iter().any(|(other, _)| other == name) =>
+                    {
+                        name.to_owned()
+                    }
+                    // Names that are missing, taken or not valid tile names fall back to the file
+                    // name, which renames.txt can override.
+                    _ => {
+                        unnamed.push(format!("{}=", image.stem));
+                        image.stem.clone()
+                    }
+                },
+                (None, None) => image.stem.clone(),
             };
-            if name.contains(&['_', '[', ']'][..]) {
-                println!("ERROR: Illegal name: {name:?}");
-                exit(1);
-            }
+            tiles.push((name, image));
+        }
+        if !unnamed.is_empty() {
+            println!(
+                "WARNING: {} files have no display name of their own, see unnamed.txt",
+                unnamed.len()
+            );
+            write_lines(&self.base.join("unnamed.txt"), &unnamed)?;
+        }
+        for (name, image) in &tiles {
+            check_name(name).map_err(|e| Error::Invalid(format!("{e} ({})", image.origin)))?;
+        }
+        Ok(tiles)
+    }
+    fn check_changes(&mut self) -> Result<()> {
+        println!("Checking tiles.");
+        let mut added = Vec::new();
+        for (name, image) in self.local_tiles()? {
             self.missing.remove(&name);
             if !self.tiles.contains_key(&name) {
-                self.added.push(name);
+                self.added.push(name.clone());
+                added.push((name, image));
             }
         }
+        self.suggest_renames(&added)
     }
-    fn confirm_changes(&mut self) {
-        let mut additions = BufWriter::new(File::create(BASE_PATH.join("additions.txt")).unwrap());
-        let mut missing = BufWriter::new(File::create(BASE_PATH.join("missing.txt")).unwrap());
-        let _ = File::create(BASE_PATH.join("todelete.txt")).unwrap();
-        for tile in &self.added {
-            writeln!(&mut additions, "{tile}").unwrap();
+    fn confirm_changes(&mut self) -> Result<()> {
+        self.report_duplicates()?;
+        write_lines(&self.base.join("additions.txt"), &self.added)?;
+        write_lines(&self.base.join("missing.txt"), &self.missing)?;
+        if !self.options.yes {
+            let path = self.base.join("todelete.txt");
+            File::create(&path).map_err(Error::io(&path))?;
+        }
+        for (_, old, new) in &self.renamed {
+            println!("Renaming tile {old:?} to {new:?}");
         }
-        for tile in &self.missing {
-            writeln!(&mut missing, "{tile}").unwrap();
+        if self.options.yes {
+            println!(
+                "Accepting {} additions and {} missing tiles without confirmation.",
+                self.added.len(),
+                self.missing.len()
+            );
+            return Ok(());
         }
-        drop(additions);
-        drop(missing);
         println!("Please confirm that the tiles being added in additions.txt are correct.");
         println!("Also please check over the tiles in missing.txt and ensure that not updating them was intentional.");
         println!("If there are tiles in missing.txt that you no longer wish to keep, please copy them to todelete.txt.");
         println!("If you need to make any changes to the tiles or renames.txt please restart this program.");
         println!("When you are done, please enter \"continue\".");
-        let mut response = String::new();
-        stdin().read_line(&mut response).unwrap();
-        if response.trim().to_lowercase() != "continue" {
-            println!("Aborting!");
-            exit(1);
-        }
+        prompt_continue()
     }
-    fn record_deletions(&mut self) {
-        let todelete = BufReader::new(File::open(BASE_PATH.join("todelete.txt")).unwrap());
+    fn record_deletions(&mut self) -> Result<()> {
+        let path = self.base.join("todelete.txt");
+        let todelete = match File::open(&path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::Io(path, e)),
+        };
         for line in todelete.lines() {
-            let name = line.unwrap();
+            let name = line.map_err(Error::io(&path))?;
             if let Some(tile) = self.tiles.remove(&name) {
-                self.deleted.push(tile.id.unwrap());
+                self.deleted.push((tile.id.unwrap(), name));
                 self.entries.remove(&tile.pos);
             } else {
                 println!("ERROR: Requested to delete tile that doesn't exist {name:?}");
             }
         }
+        if let Some(max) = self.options.max_deletions {
+            if self.deleted.len() > max {
+                return Err(Error::Aborted(format!(
+                    "{} tiles would be deleted but at most {max} are allowed",
+                    self.deleted.len()
+                )));
+            }
+        }
+        self.check_usage()
+    }
+    /// Searches the wiki for pages that still use the tiles being deleted, refusing to delete
+    /// them unless forced.
+    fn check_usage(&mut self) -> Result<()> {
+        if self.deleted.is_empty() {
+            return Ok(());
+        }
+        println!("Checking whether the tiles being deleted are still used.");
+        for (_, name) in &self.deleted {
+            let pages = self.wiki.tile_usage(&self.name, name)?;
+            if !pages.is_empty() {
+                println!("WARNING: Tile {name:?} is used on {}", pages.join(", "));
+                self.in_use.push((name.clone(), pages));
+            }
+        }
+        if self.in_use.is_empty() || self.options.dry_run {
+            return Ok(());
+        }
+        if self.options.force_deletions {
+            println!("Deleting tiles that are still used as deletions are forced.");
+            return Ok(());
+        }
+        Err(Error::Aborted(format!(
+            "{} tiles being deleted are still used, pass --force-deletions to delete them anyway",
+            self.in_use.len()
+        )))
     }
     fn lookup(&mut self, name: &str) -> TilePos {
         if let Some(tile) = self.tiles.get(name) {
             return tile.pos;
         }
         let pos = loop {
-            let pos = if self.next.1 < self.next.0 {
-                TilePos {
-                    x: self.next.1,
-                    y: self.next.0,
-                    z: self.next.2,
-                }
-            } else {
-                TilePos {
-                    x: self.next.0,
-                    y: self.next.1 - self.next.0,
-                    z: self.next.2,
-                }
-            };
-            if self.entries.get(&pos).is_none() {
+            let pos = self.options.placement.position(self.next);
+            if !self.entries.contains_key(&pos) {
                 break pos;
             }
-            self.next.1 += 1;
-            if self.next.1 > self.next.0 * 2 {
-                self.next.0 += 1;
-                self.next.1 = 0;
-                if self.next.0 == MAX_SIZE {
-                    self.next.0 = 0;
-                    self.next.2 += 1;
-                }
-            }
+            self.next += 1;
         };
         self.tiles.insert(name.to_owned(), Tile { pos, id: None });
         self.entries.insert(pos, name.to_owned());
         pos
     }
-    fn update(&mut self) {
-        println!("Updating tilesheet with new tiles.");
-        let path = BASE_PATH.join(&self.name);
-        for entry in WalkDir::new(&path) {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if !path.is_file() {
-                continue;
-            }
-            if path.extension().and_then(|x| x.to_str()) != Some("png") {
-                continue;
+    /// Gives new tiles of the same group the first run of free cells long enough for all of them,
+    /// so that they end up next to each other instead of scattered over earlier holes.
+    fn reserve(&mut self, names: &[&String]) {
+        let placement = self.options.placement;
+        let (mut start, mut end) = (self.next, self.next);
+        while end - start < names.len() as u64 {
+            end += 1;
+            if self.entries.contains_key(&placement.position(end - 1)) {
+                start = end;
             }
-            let name = path.file_stem().unwrap().to_str().unwrap();
-            let name = match self.renames.get(name) {
-                Some(name) => {
-                    if name.is_empty() {
-                        continue;
-                    }
-                    name.clone()
+        }
+        for (pos, name) in (start..end).map(|i| placement.position(i)).zip(names) {
+            self.tiles.insert(name.to_string(), Tile { pos, id: None });
+            self.entries.insert(pos, name.to_string());
+        }
+    }
+    /// Loads a tile image and turns it into a single square frame, ready to be resized.
+    fn prepare(&self, image: &TileImage) -> Result<FloatImage> {
+        let mut img = image.load()?;
+        fix_translucent(&mut img);
+        let meta = image.animation()?;
+        let img = animation::select_frame(
+            &img,
+            meta.as_ref(),
+            self.options.frame,
+            self.options.strips,
+            &image.origin,
+        )?;
+        let (w, h) = img.dimensions();
+        fit_square(img, self.options.fit).ok_or_else(|| {
+            Error::Invalid(format!("{} is {w}x{h} which is not square", image.origin))
+        })
+    }
+    fn update(&mut self) -> Result<()> {
+        println!("Updating tilesheet with new tiles.");
+        let largest = (0..self.tilesheets.len()).max_by_key(|&i| self.tilesheets[i].size);
+        if let Some(sheet) = largest.map(|i| &self.tilesheets[i]) {
+            let mut missing = self.missing.iter().collect::<Vec<_>>();
+            missing.sort();
+            for name in missing {
+                if let Some(img) = self.tiles.get(name).and_then(|tile| sheet.tile(tile.pos)) {
+                    self.preview.missing.push((name.clone(), img));
                 }
-                None => name.to_owned(),
-            };
-            if name.contains(&['_', '[', ']'][..]) {
-                println!("ERROR: Illegal name: {name:?}");
-                exit(1);
             }
-            let mut img = image::open(path).unwrap().to_rgba8();
-            fix_translucent(&mut img);
-            let img = decode_srgb(&img);
+        }
+        let placement = self.options.placement;
+        let mut tiles = self.local_tiles()?;
+        tiles
+            .sort_by_cached_key(|(name, image)| (placement.group(name, Some(image)), name.clone()));
+        let new = tiles
+            .iter()
+            .map(|(name, _)| name.clone())
+            .filter(|name| !self.tiles.contains_key(name))
+            .collect::<HashSet<_>>();
+        if placement.grouping != Grouping::None {
+            let groups = tiles.chunk_by(|(a, a_image), (b, b_image)| {
+                placement.group(a, Some(a_image)) == placement.group(b, Some(b_image))
+            });
+            for group in groups {
+                let names = group
+                    .iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| new.contains(*name))
+                    .collect::<Vec<_>>();
+                self.reserve(&names);
+            }
+        }
+        for (name, image) in tiles {
+            let img = self.prepare(&image)?;
+            let (img, filter, pixel_art) = self.scaling(img, Some(&image.stem));
+            let added = new.contains(&name);
             let pos = self.lookup(&name);
+            let old = largest.and_then(|i| self.tilesheets[i].tile(pos));
             for tilesheet in &mut self.tilesheets {
-                tilesheet.insert(pos, &img);
+                tilesheet.insert(pos, &img, filter, pixel_art);
+            }
+            if let Some(new) = largest.and_then(|i| self.tilesheets[i].tile(pos)) {
+                match old {
+                    _ if added => self.preview.added.push((name, new)),
+                    Some(old) if old != new => self.preview.changed.push((name, old, new)),
+                    _ => {}
+                }
             }
         }
+        Ok(())
     }
-    fn optimize(&self) {
+    /// Shrinks pixel art stored enlarged back to one pixel per texel, so that it can be enlarged
+    /// by a whole factor to each size that allows it, and picks the filter for the tile. Returns
+    /// whether the tile is pixel art.
+    fn scaling(&self, img: FloatImage, stem: Option<&str>) -> (FloatImage, Filter, bool) {
+        let block = pixel_grid(&img);
+        let img = if block > 1 {
+            resize(
+                &img,
+                img.width() / block,
+                img.height() / block,
+                Filter::Nearest,
+            )
+        } else {
+            img
+        };
+        match stem.and_then(|stem| self.filters.get(stem)) {
+            Some(&filter) => (img, filter, false),
+            None => {
+                let pixel_art = img.width() > 0 && (block > 1 || img.width() <= PIXEL_ART_SIZE);
+                (img, self.options.filter.unwrap_or_default(), pixel_art)
+            }
+        }
+    }
+    fn optimize(&self) -> Result<()> {
         println!("Optimizing tilesheets");
-        let optipng = self
-            .tilesheets
-            .iter()
-            .flat_map(|tilesheet| {
-                tilesheet.layers.iter().enumerate().map(move |(z, layer)| {
-                    let name = format!("Tilesheet {} {} {}.png", self.name, tilesheet.size, z);
-                    let path = BASE_PATH.join(name);
-                    layer.save(&path).unwrap();
-                    spawn(|| {
-                        oxipng::optimize(
-                            &oxipng::InFile::Path(path),
-                            &oxipng::OutFile::Path(None),
-                            &oxipng::Options::max_compression(),
-                        )
-                    })
-                })
-            })
-            .collect::<Vec<_>>();
-        for child in optipng {
-            child.join().unwrap().unwrap();
+        let mut optipng = Vec::new();
+        for tilesheet in &self.tilesheets {
+            for (z, layer) in tilesheet.changed_layers() {
+                let name = format!("Tilesheet {} {} {}.png", self.name, tilesheet.size, z);
+                let path = self.base.join(&name);
+                layer.save(&path).map_err(Error::image(&name))?;
+                let input = oxipng::InFile::Path(path.clone());
+                let child = spawn(move || {
+                    oxipng::optimize(
+                        &input,
+                        &oxipng::OutFile::Path(None),
+                        &oxipng::Options::max_compression(),
+                    )
+                });
+                optipng.push((path, child));
+            }
         }
+        for (path, child) in optipng {
+            let result = child.join().expect("optimizer thread panicked");
+            result.map_err(|e| Error::Optimize(path, e))?;
+        }
+        Ok(())
     }
-    fn upload_sheets(&self) {
-        println!("Uploading tilesheets.");
-        let token = &self.mw.get_token().unwrap();
-        let failed_uploads = self
+    /// Has the rebuilt tilesheet approved and carries out the planned changes, or writes the
+    /// preview and report on a dry run. Images of retired sizes are listed at the end.
+    fn finish(&self, unused: &[String]) -> Result<()> {
+        if self.options.dry_run {
+            self.write_preview()?;
+            self.optimize()?;
+            self.write_report()?;
+        } else {
+            self.confirm_preview()?;
+            self.optimize()?;
+            let mut journal = self.plan();
+            journal.save(&self.base, &self.name)?;
+            self.apply(&mut journal)?;
+        }
+        if !unused.is_empty() {
+            println!("The following tilesheet images are no longer used and can be deleted:");
+            for name in unused {
+                println!("{name}");
+            }
+        }
+        println!("Done");
+        Ok(())
+    }
+    /// Records everything the rest of the update will do to the wiki.
+    fn plan(&self) -> Journal {
+        let layers = self
             .tilesheets
             .iter()
             .flat_map(|tilesheet| {
-                tilesheet.layers.iter().enumerate().flat_map(move |(z, _)| {
-                    let name = format!("Tilesheet {} {} {}.png", self.name, tilesheet.size, z);
-                    let path = BASE_PATH.join(&name);
-                    let result = self
-                        .mw
-                        .upload(
-                            &name,
-                            token,
-                            Upload::File(&path),
-                            Some("[[Category:Tilesheets]]"),
-                            Some("Tilesheet uploaded by ftb-rs"),
-                            false,
-                        )
-                        .unwrap();
-                    match result["upload"]["result"]
-                        .as_str()
-                        .unwrap_or_else(|| panic!("could not find upload result: {result:?}"))
-                    {
-                        "Warning" => {
-                            let warnings = result["upload"]["warnings"]
-                                .as_object()
-                                .unwrap()
-                                .iter()
-                                .map(|(warning, value)| (warning.clone(), value.clone()))
-                                .collect::<Vec<_>>();
-                            let filekey = result["upload"]["filekey"].as_str().unwrap().to_string();
-                            Some((name, filekey, warnings))
-                        }
-                        "Success" => None,
-                        other => panic!("Unknown result: {other}"),
-                    }
+                tilesheet.changed_layers().map(move |(z, _)| {
+                    format!("Tilesheet {} {} {}.png", self.name, tilesheet.size, z)
                 })
             })
-            .collect::<Vec<_>>();
+            .collect();
+        let added = self
+            .added
+            .iter()
+            .map(|name| (name.clone(), self.tiles[name].pos))
+            .collect();
+        Journal {
+            layers,
+            sizes: self.new_sizes.clone(),
+            renamed: self.renamed.clone(),
+            moved: self.moved.clone(),
+            deleted: self.deleted.clone(),
+            added,
+            emptied: self.emptied.clone(),
+            ..Journal::default()
+        }
+    }
+    /// Carries out the remaining steps of a journal, saving it after each one.
+    fn apply(&self, journal: &mut Journal) -> Result<()> {
+        self.upload_sheets(journal)?;
+        self.edit_sizes(journal)?;
+        self.rename_tiles(journal)?;
+        self.move_tiles(journal)?;
+        self.delete_tiles(journal)?;
+        self.add_tiles(journal)?;
+        self.delete_layers(journal)?;
+        Journal::remove(&self.base, &self.name)
+    }
+    fn upload_sheets(&self, journal: &mut Journal) -> Result<()> {
+        println!("Uploading changed tilesheets.");
+        let mut failed_uploads = Vec::new();
+        for name in journal.layers.clone() {
+            if journal.uploaded.contains(&name) {
+                continue;
+            }
+            let path = self.base.join(&name);
+            match self.wiki.upload(&name, &path)? {
+                UploadResult::Warning { filekey, warnings } => {
+                    failed_uploads.push((name, filekey, warnings))
+                }
+                UploadResult::Success | UploadResult::NoChange => {
+                    journal.uploaded.push(name);
+                    journal.save(&self.base, &self.name)?;
+                }
+            }
+        }
         if failed_uploads.is_empty() {
-            return;
+            return Ok(());
         }
         println!("Encountered the following warnings while uploading tilesheets:");
         for (name, _, warnings) in &failed_uploads {
@@ -396,114 +759,1043 @@ impl TilesheetManager {
                 println!("[{name}] {warning}: {value}");
             }
         }
-        println!("To proceed with file uploads, please enter \"continue\".");
-        let mut response = String::new();
-        stdin().read_line(&mut response).unwrap();
-        if response.trim().to_lowercase() != "continue" {
-            println!("Aborting!");
-            exit(1);
+        if self.options.allow_upload_warnings {
+            println!("Proceeding with file uploads despite warnings.");
+        } else if self.options.yes {
+            return Err(Error::Aborted(
+                "upload warnings require --allow-upload-warnings".into(),
+            ));
+        } else {
+            println!("To proceed with file uploads, please enter \"continue\".");
+            prompt_continue()?;
         }
         for (name, filekey, _) in failed_uploads {
-            let result = match self.mw.upload(
-                &name,
-                token,
-                Upload::Filekey(&filekey),
-                Some("[[Category:Tilesheets]]"),
-                Some("Tilesheet uploaded by ftb-rs"),
-                true,
-            ) {
-                Ok(x) => x,
-                Err(MwError::Json(err))
-                    if err["error"]["code"].as_str() == Some("fileexists-no-change") =>
-                {
-                    continue
-                }
-                Err(e) => {
-                    panic!("failed to upload image: {e:?}");
-                }
-            };
-            match result["upload"]["result"]
-                .as_str()
-                .unwrap_or_else(|| panic!("could not find upload result: {result:?}"))
-            {
-                "Warning" => (),
-                "Success" => (),
-                other => panic!("Unknown result: {other}"),
-            }
+            self.wiki.upload_filekey(&name, &filekey)?;
+            journal.uploaded.push(name);
+            journal.save(&self.base, &self.name)?;
+        }
+        Ok(())
+    }
+    /// Sets the new sizes once their images are uploaded, so pages never use a missing layer.
+    fn edit_sizes(&self, journal: &mut Journal) -> Result<()> {
+        if let Some(sizes) = &journal.sizes {
+            println!("Changing tilesheet sizes to {sizes:?}.");
+            self.wiki.edit_sheet(&self.name, sizes)?;
+            journal.sizes = None;
+            journal.save(&self.base, &self.name)?;
+        }
+        Ok(())
+    }
+    fn rename_tiles(&self, journal: &mut Journal) -> Result<()> {
+        println!("Renaming tiles.");
+        while let Some((id, _, new)) = journal.renamed.get(journal.renamed_done) {
+            self.wiki.edit_tile(*id, Some(new), None)?;
+            journal.renamed_done += 1;
+            journal.save(&self.base, &self.name)?;
         }
+        Ok(())
     }
-    fn delete_tiles(&self) {
+    /// Deletes the images of layers that repacking left empty, once no tile points at them.
+    fn delete_layers(&self, journal: &mut Journal) -> Result<()> {
+        if journal.emptied.is_empty() {
+            return Ok(());
+        }
+        println!("Deleting empty tilesheet images.");
+        while let Some(name) = journal.emptied.get(journal.emptied_done) {
+            self.wiki.delete_file(name)?;
+            journal.emptied_done += 1;
+            journal.save(&self.base, &self.name)?;
+        }
+        Ok(())
+    }
+    fn move_tiles(&self, journal: &mut Journal) -> Result<()> {
+        if journal.moved.is_empty() {
+            return Ok(());
+        }
+        println!("Moving tiles.");
+        while let Some(&(id, _, pos)) = journal.moved.get(journal.moved_done) {
+            self.wiki.edit_tile(id, None, Some(pos))?;
+            journal.moved_done += 1;
+            journal.save(&self.base, &self.name)?;
+        }
+        Ok(())
+    }
+    fn delete_tiles(&self, journal: &mut Journal) -> Result<()> {
         println!("Deleting old tiles that are no longer needed.");
-        let token = self.mw.get_token().unwrap();
-        for chunk in self.deleted.chunks(50) {
-            let tiles = chunk
-                .iter()
-                .map(|id| id.to_string())
-                .collect::<Vec<_>>()
-                .join("|");
-            if let Err(e) = self
-                .mw
-                .delete_tiles(&token, &tiles, Some("ftb-rs deleting tiles"))
-            {
-                println!("ERROR: {e:?}");
-            }
+        while let Some(chunk) = journal
+            .deleted
+            .chunks(CHUNK_SIZE)
+            .nth(journal.deleted_chunks)
+        {
+            let ids = chunk.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+            self.wiki.delete_tiles(&ids)?;
+            journal.deleted_chunks += 1;
+            journal.save(&self.base, &self.name)?;
+        }
+        // The list is done with, so later runs that accept changes without prompting do not
+        // delete the same names again.
+        let path = self.base.join("todelete.txt");
+        if path.is_file() {
+            File::create(&path).map_err(Error::io(&path))?;
         }
+        Ok(())
     }
-    fn add_tiles(&self) {
+    /// Drops the tiles still to be added that the wiki already has. An update can be interrupted
+    /// after the wiki added a chunk of tiles but before the journal recorded it, and adding them
+    /// again would register them twice.
+    fn skip_registered(&self, journal: &mut Journal) -> Result<()> {
+        let done = (journal.added_chunks * CHUNK_SIZE).min(journal.added.len());
+        if done == journal.added.len() {
+            return Ok(());
+        }
+        let mut registered = HashSet::new();
+        for tile in self.wiki.query_tiles(&self.name) {
+            registered.insert(tile?.name);
+        }
+        let pending = journal.added.split_off(done);
+        let count = pending.len();
+        journal.added.extend(
+            pending
+                .into_iter()
+                .filter(|(name, _)| !registered.contains(name)),
+        );
+        let skipped = count - (journal.added.len() - done);
+        if skipped > 0 {
+            println!("Skipping {skipped} tiles that were already added.");
+            journal.save(&self.base, &self.name)?;
+        }
+        Ok(())
+    }
+    fn add_tiles(&self, journal: &mut Journal) -> Result<()> {
         println!("Adding new tiles.");
-        let token = self.mw.get_token().unwrap();
-        for chunk in self.added.chunks(50) {
+        while let Some(chunk) = journal.added.chunks(CHUNK_SIZE).nth(journal.added_chunks) {
             let tiles = chunk
                 .iter()
-                .map(|name| {
-                    let tile = &self.tiles[name];
-                    format!("{} {} {} {}", tile.pos.x, tile.pos.y, tile.pos.z, name)
+                .map(|(name, pos)| (&**name, *pos))
+                .collect::<Vec<_>>();
+            self.wiki.add_tiles(&self.name, &tiles)?;
+            journal.added_chunks += 1;
+            journal.save(&self.base, &self.name)?;
+        }
+        Ok(())
+    }
+    fn extract(&self, size: ExtractSize, overwrite: bool) -> Result<()> {
+        println!("Extracting tiles.");
+        let sheets = match size {
+            ExtractSize::Largest => self
+                .tilesheets
+                .iter()
+                .max_by_key(|sheet| sheet.size)
+                .into_iter()
+                .map(|sheet| (sheet, self.base.join(&self.name)))
+                .collect::<Vec<_>>(),
+            ExtractSize::Size(size) => self
+                .tilesheets
+                .iter()
+                .filter(|sheet| sheet.size == size)
+                .map(|sheet| (sheet, self.base.join(&self.name)))
+                .collect(),
+            ExtractSize::All => self
+                .tilesheets
+                .iter()
+                .map(|sheet| {
+                    (
+                        sheet,
+                        self.base.join(format!("{} {}", self.name, sheet.size)),
+                    )
                 })
-                .collect::<Vec<_>>()
-                .join("|");
-            if let Err(e) =
-                self.mw
-                    .add_tiles(&token, &self.name, &tiles, Some("ftb-rs adding tiles"))
-            {
-                println!("ERROR: {e:?}");
+                .collect(),
+        };
+        if sheets.is_empty() {
+            return Err(Error::Invalid(
+                "the tilesheet has no images of the requested size".into(),
+            ));
+        }
+        let mut names = self.tiles.keys().collect::<Vec<_>>();
+        names.sort();
+        for (sheet, dir) in sheets {
+            create_dir_all(&dir).map_err(Error::io(&dir))?;
+            let (mut written, mut skipped) = (0, 0);
+            for name in &names {
+                if name.contains(&['/', '\\', ':', '*', '?', '"', '<', '>', '|'][..]) {
+                    println!("WARNING: Cannot use {name:?} as a file name");
+                    continue;
+                }
+                let pos = self.tiles[*name].pos;
+                let img = match sheet.tile(pos) {
+                    Some(img) => img,
+                    None => {
+                        println!(
+                            "WARNING: {name:?} lies outside the size {} tilesheet",
+                            sheet.size
+                        );
+                        continue;
+                    }
+                };
+                let path = dir.join(format!("{name}.png"));
+                if path.exists() && !overwrite {
+                    skipped += 1;
+                    continue;
+                }
+                img.save(&path).map_err(Error::image(path.display()))?;
+                written += 1;
+            }
+            println!(
+                "Extracted {written} tiles at size {} into {}, skipped {skipped} existing files.",
+                sheet.size,
+                dir.display()
+            );
+        }
+        Ok(())
+    }
+    fn write_report(&self) -> Result<()> {
+        let mut lines = vec![format!("Dry run report for {}", self.name), String::new()];
+        lines.push("Tilesheet images that would be uploaded:".into());
+        for tilesheet in &self.tilesheets {
+            for (z, layer) in tilesheet.changed_layers() {
+                let (w, h) = layer.dimensions();
+                let name = format!("Tilesheet {} {} {}.png", self.name, tilesheet.size, z);
+                lines.push(format!("{name} ({w}x{h})"));
             }
         }
+        if !self.emptied.is_empty() {
+            lines.push(String::new());
+            lines.push("Tilesheet images that would be deleted:".into());
+            lines.extend(self.emptied.iter().cloned());
+        }
+        if let Some(sizes) = &self.new_sizes {
+            lines.push(format!("Sizes would be changed to {sizes:?}"));
+        }
+        lines.push(String::new());
+        lines.push("Tiles that would be added:".into());
+        for name in &self.added {
+            let TilePos { x, y, z } = self.tiles[name].pos;
+            lines.push(format!("{x} {y} {z} {name}"));
+        }
+        lines.push(String::new());
+        lines.push("Tiles that would be renamed:".into());
+        for (id, old, new) in &self.renamed {
+            lines.push(format!("{id} {old} => {new}"));
+        }
+        lines.push(String::new());
+        lines.push("Tiles that would be moved:".into());
+        for (id, name, TilePos { x, y, z }) in &self.moved {
+            lines.push(format!("{id} {x} {y} {z} {name}"));
+        }
+        lines.push(String::new());
+        lines.push("Tiles that would be deleted:".into());
+        for (id, name) in &self.deleted {
+            lines.push(format!("{id} {name}"));
+        }
+        lines.push(String::new());
+        lines.push("Tiles being deleted that pages still use:".into());
+        for (name, pages) in &self.in_use {
+            lines.push(format!("{name}: {}", pages.join(", ")));
+        }
+        lines.push(String::new());
+        lines.push("Tiles missing from the local files:".into());
+        lines.extend(self.missing.iter().cloned());
+        let path = self.base.join("dryrun.txt");
+        write_lines(&path, &lines)?;
+        println!("Wrote dry run report to {}", path.display());
+        Ok(())
+    }
+}
+/// Which sizes of a tilesheet to extract tiles from.
+#[derive(Clone, Copy, Debug)]
+pub enum ExtractSize {
+    /// The largest size, written to `tilesheets/<mod>/`.
+    Largest,
+    /// A single size, written to `tilesheets/<mod>/`.
+    Size(u32),
+    /// Every size, each written to `tilesheets/<mod> <size>/`.
+    All,
+}
+/// Tile names may not contain characters that the tile templates treat specially.
+fn check_name(name: &str) -> Result<()> {
+    if name.contains(&['_', '[', ']'][..]) {
+        return Err(Error::Invalid(format!("illegal tile name {name:?}")));
+    }
+    Ok(())
+}
+/// Writes each item on its own line, replacing the file.
+fn write_lines<I>(path: &Path, lines: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+{
+    let file = File::create(path).map_err(Error::io(path))?;
+    let mut file = BufWriter::new(file);
+    for line in lines {
+        writeln!(&mut file, "{line}").map_err(Error::io(path))?;
     }
+    file.flush().map_err(Error::io(path))
 }
-fn load_renames(name: &str) -> HashMap<String, String> {
-    let path = BASE_PATH.join(name);
-    match File::open(path.join("renames.txt")) {
+/// Parses a comma separated list of tilesheet sizes, which must be distinct and not zero.
+pub fn parse_sizes(sizes: &str) -> Option<Vec<u32>> {
+    let mut parsed = Vec::new();
+    for size in sizes.split(',') {
+        match size.trim().parse() {
+            Ok(size) if size > 0 && !parsed.contains(&size) => parsed.push(size),
+            _ => return None,
+        }
+    }
+    Some(parsed)
+}
+/// Loads renames.txt, returning the file renames and the tile renames in file order.
+/// Lines of the form `file=Name` give the wiki name for a local file, while lines of the form
+/// `Old Name => New Name` rename a tile that is already registered on the wiki, so that chained
+/// renames apply one after another. Blank lines and lines starting with `#` are ignored.
+type Renames = (HashMap<String, String>, Vec<(String, String)>);
+fn load_renames(base: &Path, name: &str) -> Result<Renames> {
+    let path = base.join(name).join("renames.txt");
+    let mut files = HashMap::new();
+    let mut tiles = Vec::new();
+    match File::open(&path) {
         Ok(mut file) => {
+            let tile_reg = Regex::new(r"^(.*?)\s*=>\s*(.*)$").unwrap();
             let reg = Regex::new("(.*)=(.*)").unwrap();
             let mut s = String::new();
-            file.read_to_string(&mut s).unwrap();
-            s.lines()
-                .filter_map(|line| match reg.captures(line) {
-                    Some(cap) => Some((cap[1].to_owned(), cap[2].to_owned())),
-                    None => {
-                        println!("WARNING: Invalid line in renames.txt {line:?}");
-                        None
-                    }
-                })
-                .collect()
+            file.read_to_string(&mut s).map_err(Error::io(&path))?;
+            for line in s.lines() {
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some(cap) = tile_reg.captures(line) {
+                    tiles.push((cap[1].to_owned(), cap[2].to_owned()));
+                } else if let Some(cap) = reg.captures(line) {
+                    files.insert(cap[1].to_owned(), cap[2].to_owned());
+                } else {
+                    println!("WARNING: Invalid line in renames.txt {line:?}");
+                }
+            }
         }
         Err(e) => {
             println!("WARNING: Failed to load renames.txt {e:?}");
-            HashMap::new()
         }
     }
+    Ok((files, tiles))
+}
+/// Loads filters.txt, returning the mod's default filter and the filters for individual files.
+/// Each line has the form `file=filter`, and a line of the form `*=filter` sets the default.
+fn load_filters(base: &Path, name: &str) -> Result<(Option<Filter>, HashMap<String, Filter>)> {
+    let path = base.join(name).join("filters.txt");
+    let mut default = None;
+    let mut filters = HashMap::new();
+    let s = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((default, filters)),
+        Err(e) => return Err(Error::Io(path, e)),
+    };
+    for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let filter = line
+            .split_once('=')
+            .and_then(|(file, filter)| Some((file.trim(), Filter::parse(filter.trim())?)));
+        match filter {
+            Some(("*", filter)) => default = Some(filter),
+            Some((file, filter)) => {
+                filters.insert(file.to_owned(), filter);
+            }
+            None => println!("WARNING: Invalid line in filters.txt {line:?}"),
+        }
+    }
+    Ok((default, filters))
+}
+/// Logs in to the wiki with the account in ftb.json.
+pub fn login() -> Result<Mediawiki> {
+    Ok(Mediawiki::login_path("ftb.json")?)
+}
+/// Starts a manager for a mod whose tilesheet must already exist, with its tiles imported.
+fn existing_tilesheet<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    options: Options,
+) -> Result<TilesheetManager<B>> {
+    let mut manager = TilesheetManager::new(wiki, base, name, options)?;
+    if !manager.import_existing_tilesheets()? {
+        return Err(Error::Invalid(format!("no tilesheet found for {name:?}")));
+    }
+    manager.import_tiles()?;
+    Ok(manager)
+}
+/// Fails if an earlier update of the mod did not finish. Dry runs are refused too, as they write
+/// the same tilesheet images that resuming would upload.
+fn check_unfinished(base: &Path, name: &str) -> Result<()> {
+    if Journal::load(base, name)?.is_some() {
+        return Err(Error::Invalid(format!(
+            "an earlier update of {name:?} did not finish, run `ftb resume {name}` first \
+             or drop it with `ftb resume {name} --discard`"
+        )));
+    }
+    Ok(())
+}
+/// Updates a mod's tilesheet from the tiles in its folder below `base`.
+pub fn update_tilesheet<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    options: Options,
+) -> Result<()> {
+    check_unfinished(base, name)?;
+    let mut manager = TilesheetManager::new(wiki, base, name, options)?;
+    manager.import_tilesheets()?;
+    manager.import_tiles()?;
+    manager.record_renames()?;
+    manager.check_changes()?;
+    if manager.options.dry_run {
+        manager.report_duplicates()?;
+    } else {
+        manager.confirm_changes()?;
+    }
+    manager.record_deletions()?;
+    manager.update()?;
+    manager.finish(&[])
+}
+/// Finishes an update that was interrupted after it started modifying the wiki.
+pub fn resume_update<B: Backend>(wiki: B, base: &Path, name: &str, options: Options) -> Result<()> {
+    let mut journal = Journal::load(base, name)?
+        .ok_or_else(|| Error::Invalid(format!("there is no unfinished update of {name:?}")))?;
+    for layer in &journal.layers {
+        let path = base.join(layer);
+        if !journal.uploaded.contains(layer) && !path.is_file() {
+            return Err(Error::Invalid(format!(
+                "the tilesheet image {} is missing",
+                path.display()
+            )));
+        }
+    }
+    println!(
+        "Resuming update with {}/{} images uploaded and {}/{} tiles renamed.",
+        journal.uploaded.len(),
+        journal.layers.len(),
+        journal.renamed_done,
+        journal.renamed.len(),
+    );
+    let manager = TilesheetManager::new(wiki, base, name, options)?;
+    manager.skip_registered(&mut journal)?;
+    manager.apply(&mut journal)?;
+    println!("Done");
+    Ok(())
+}
+/// Drops the plan of an unfinished update, so that a new update can run. Whatever the update
+/// already changed on the wiki stays as it is.
+pub fn discard_update(base: &Path, name: &str) -> Result<()> {
+    let journal = Journal::load(base, name)?
+        .ok_or_else(|| Error::Invalid(format!("there is no unfinished update of {name:?}")))?;
+    Journal::remove(base, name)?;
+    println!(
+        "Discarded the unfinished update with {}/{} images uploaded and {}/{} tiles renamed.",
+        journal.uploaded.len(),
+        journal.layers.len(),
+        journal.renamed_done,
+        journal.renamed.len(),
+    );
+    Ok(())
+}
+/// Moves every tile of a mod into a compact layout, rebuilding and uploading the tilesheet and
+/// updating the position of each tile on the wiki.
+pub fn repack_tilesheet<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    options: Options,
+) -> Result<()> {
+    check_unfinished(base, name)?;
+    let mut manager = existing_tilesheet(wiki, base, name, options)?;
+    manager.repack()?;
+    manager.finish(&[])
+}
+/// Adds sizes to an existing tilesheet, rendering every tile at them, and retires sizes that are
+/// no longer wanted.
+pub fn change_sizes<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    add: &[u32],
+    remove: &[u32],
+    options: Options,
+) -> Result<()> {
+    check_unfinished(base, name)?;
+    let mut manager = existing_tilesheet(wiki, base, name, options)?;
+    let unused = manager.change_sizes(add, remove)?;
+    manager.finish(&unused)
+}
+/// Cuts every registered tile out of the existing tilesheet images and saves them as PNGs.
+pub fn extract_tiles<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    size: ExtractSize,
+    overwrite: bool,
+) -> Result<()> {
+    let manager = existing_tilesheet(wiki, base, name, Options::default())?;
+    manager.extract(size, overwrite)?;
+    println!("Done");
+    Ok(())
+}
+/// Checks the registered tiles against the tilesheet images, returning true if all is well.
+pub fn verify_tilesheet<B: Backend>(wiki: B, base: &Path, name: &str) -> Result<bool> {
+    let manager = existing_tilesheet(wiki, base, name, Options::default())?;
+    let problems = manager.verify();
+    if problems == 0 {
+        println!("No problems found.");
+    } else {
+        println!("Found {problems} problems.");
+    }
+    Ok(problems == 0)
+}
+/// Prints the tiles an update would add, rename and leave missing, without changing anything.
+pub fn diff_tilesheet<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    options: Options,
+) -> Result<()> {
+    let mut manager = TilesheetManager::new(wiki, base, name, options)?;
+    // The tilesheet images are only needed to suggest renames.
+    manager.import_existing_tilesheets()?;
+    manager.import_tiles()?;
+    manager.record_renames()?;
+    manager.check_changes()?;
+    let mut added = manager.added.clone();
+    added.sort();
+    let mut missing = manager.missing.iter().collect::<Vec<_>>();
+    missing.sort();
+    for name in &added {
+        println!("+ {name}");
+    }
+    for (_, old, new) in &manager.renamed {
+        println!("~ {old} => {new}");
+    }
+    for name in missing {
+        println!("- {name}");
+    }
+    Ok(())
 }
-pub fn update_tilesheet(name: &str) {
-    let mut manager = TilesheetManager::new(name);
-    manager.import_tilesheets();
-    manager.import_tiles();
-    manager.check_changes();
-    manager.confirm_changes();
-    manager.record_deletions();
-    manager.update();
-    manager.optimize();
-    manager.upload_sheets();
-    manager.delete_tiles();
-    manager.add_tiles();
+/// Sets the translated names and descriptions of a mod's tiles from its language files.
+pub fn sync_translations<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    options: Options,
+) -> Result<()> {
+    let names = options.source.names(base, name)?;
+    let mut manager = TilesheetManager::new(wiki, base, name, options)?;
+    manager.import_tiles()?;
+    manager.sync_translations(&names)?;
     println!("Done");
+    Ok(())
+}
+/// Reports tiles that are identical or look alike, across the local files and the tilesheet.
+pub fn find_duplicates<B: Backend>(
+    wiki: B,
+    base: &Path,
+    name: &str,
+    options: Options,
+) -> Result<()> {
+    let mut manager = TilesheetManager::new(wiki, base, name, options)?;
+    manager.import_existing_tilesheets()?;
+    manager.import_tiles()?;
+    manager.report_duplicates()?;
+    println!("Done");
+    Ok(())
+}
+/// Prints every tilesheet on the wiki along with its sizes.
+pub fn list_tilesheets<B: Backend>(wiki: B) -> Result<()> {
+    for sheet in wiki.query_sheets() {
+        match sheet {
+            Ok(sheet) => println!("{} {:?}", sheet.name, sheet.sizes),
+            Err(e) => println!("WARNING: Error while querying tilesheets: {e}"),
+        }
+    }
+    Ok(())
+}
+/// Prints every tile registered to a mod, ordered by position.
+pub fn list_tiles<B: Backend>(wiki: B, name: &str) -> Result<()> {
+    let mut tiles = wiki
+        .query_tiles(name)
+        .into_iter()
+        .filter_map(|tile| match tile {
+            Ok(tile) => Some(tile),
+            Err(e) => {
+                println!("WARNING: Error while querying tiles: {e}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    tiles.sort_by_key(|tile| (tile.pos.z, tile.pos.y, tile.pos.x));
+    for tile in tiles {
+        let TilePos { x, y, z } = tile.pos;
+        println!("{x} {y} {z} {} (id {})", tile.name, tile.id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::MemoryWiki;
+    use image::{codecs::png::PngEncoder, ColorType, ImageEncoder, Rgba};
+    use std::ops::Deref;
+
+    const RED: Rgba<u8> = Rgba([200, 30, 30, 255]);
+    const GREEN: Rgba<u8> = Rgba([30, 200, 30, 255]);
+    const BLUE: Rgba<u8> = Rgba([30, 30, 200, 255]);
+    const YELLOW: Rgba<u8> = Rgba([200, 200, 30, 255]);
+
+    /// A base folder of its own for each test, with an empty folder for the mod, which is
+    /// removed when the test ends.
+    struct Base(PathBuf);
+    impl Base {
+        fn new(name: &str) -> Base {
+            let id = std::process::id();
+            let path = std::env::temp_dir().join(format!("ftb-test-{id}-{name}"));
+            let _ = fs::remove_dir_all(&path);
+            create_dir_all(path.join(name)).unwrap();
+            Base(path)
+        }
+    }
+    impl Deref for Base {
+        type Target = Path;
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+    impl Drop for Base {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+    fn png(img: &RgbaImage) -> Vec<u8> {
+        let mut data = Vec::new();
+        let (w, h) = img.dimensions();
+        PngEncoder::new(&mut data)
+            .write_image(img.as_raw(), w, h, ColorType::Rgba8)
+            .unwrap();
+        data
+    }
+    fn write_tile(base: &Path, name: &str, file: &str, color: Rgba<u8>) {
+        let path = base.join(name).join(format!("{file}.png"));
+        RgbaImage::from_pixel(16, 16, color).save(path).unwrap();
+    }
+    fn pixel(wiki: &MemoryWiki, name: &str, size: u32, pos: TilePos) -> Rgba<u8> {
+        let file = format!("Tilesheet {name} {size} {}.png", pos.z);
+        let data = wiki.file(&file).unwrap();
+        let layer = image::load_from_memory(&data).unwrap().to_rgba8();
+        *layer.get_pixel(pos.x * size + size / 2, pos.y * size + size / 2)
+    }
+    fn tile(wiki: &MemoryWiki, name: &str, tile: &str) -> Option<backend::TileInfo> {
+        wiki.tiles(name).into_iter().find(|info| info.name == tile)
+    }
+
+    #[test]
+    fn update_creates_tilesheet() {
+        let name = "Test Create";
+        let base = Base::new(name);
+        write_tile(&base, name, "Iron Ingot", RED);
+        write_tile(&base, name, "Gold Ingot", GREEN);
+        let wiki = MemoryWiki::new();
+        let options = Options {
+            yes: true,
+            sizes: Some(vec![16, 32]),
+            ..Options::default()
+        };
+        update_tilesheet(wiki.clone(), &base, name, options).unwrap();
+        assert_eq!(wiki.sizes(name), Some(vec![16, 32]));
+        let mut tiles = wiki.tiles(name);
+        tiles.sort_by(|a, b| a.name.cmp(&b.name));
+        let names = tiles.iter().map(|tile| &*tile.name).collect::<Vec<_>>();
+        assert_eq!(names, ["Gold Ingot", "Iron Ingot"]);
+        assert_ne!(tiles[0].pos, tiles[1].pos);
+        for size in [16, 32] {
+            assert_eq!(pixel(&wiki, name, size, tiles[0].pos), GREEN);
+            assert_eq!(pixel(&wiki, name, size, tiles[1].pos), RED);
+        }
+        assert!(Journal::load(&base, name).unwrap().is_none());
+    }
+    #[test]
+    fn update_fails_without_sizes() {
+        let name = "Test No Sizes";
+        let base = Base::new(name);
+        write_tile(&base, name, "Iron Ingot", RED);
+        let wiki = MemoryWiki::new();
+        let options = Options {
+            yes: true,
+            ..Options::default()
+        };
+        let result = update_tilesheet(wiki.clone(), &base, name, options);
+        assert!(matches!(result, Err(Error::Aborted(_))));
+        assert_eq!(wiki.sizes(name), None);
+        assert!(wiki.tiles(name).is_empty());
+    }
+    #[test]
+    fn dry_run_leaves_wiki_alone() {
+        let name = "Test Dry Run";
+        let base = Base::new(name);
+        write_tile(&base, name, "Iron Ingot", RED);
+        let report = base.join("dryrun.txt");
+        let wiki = MemoryWiki::new();
+        let options = Options {
+            dry_run: true,
+            yes: true,
+            sizes: Some(vec![16]),
+            ..Options::default()
+        };
+        update_tilesheet(wiki.clone(), &base, name, options).unwrap();
+        assert_eq!(wiki.sizes(name), None);
+        assert!(wiki.tiles(name).is_empty());
+        assert!(wiki.file(&format!("Tilesheet {name} 16 0.png")).is_none());
+        assert!(report.is_file());
+    }
+    #[test]
+    fn update_renames_and_deletes_tiles() {
+        let name = "Test Edit";
+        let base = Base::new(name);
+        let mut layer = RgbaImage::new(16, 32);
+        imageops::replace(&mut layer, &RgbaImage::from_pixel(16, 16, RED), 0, 0);
+        imageops::replace(&mut layer, &RgbaImage::from_pixel(16, 16, GREEN), 0, 16);
+        let old = TilePos { x: 0, y: 0, z: 0 };
+        let unused = TilePos { x: 0, y: 1, z: 0 };
+        let wiki = MemoryWiki::new()
+            .with_sheet(name, &[16])
+            .with_file(&format!("Tilesheet {name} 16 0.png"), png(&layer))
+            .with_tile(name, "Old Ingot", old)
+            .with_tile(name, "Unused", unused)
+            .with_page("Guide", &format!("{{{{Grid|Unused|mod={name}}}}}"));
+        let id = tile(&wiki, name, "Old Ingot").unwrap().id;
+        fs::write(
+            base.join(name).join("renames.txt"),
+            "Old Ingot => New Ingot\n",
+        )
+        .unwrap();
+        write_tile(&base, name, "New Ingot", BLUE);
+        write_tile(&base, name, "Copper Ingot", YELLOW);
+        let todelete = base.join("todelete.txt");
+        fs::write(&todelete, "Unused\n").unwrap();
+        // The layer already exists, which the wiki warns about.
+        let options = Options {
+            yes: true,
+            allow_upload_warnings: true,
+            ..Options::default()
+        };
+
+        // The page still uses the tile, so nothing happens unless deletions are forced.
+        let result = update_tilesheet(wiki.clone(), &base, name, options.clone());
+        assert!(matches!(result, Err(Error::Aborted(_))));
+        assert!(tile(&wiki, name, "Old Ingot").is_some());
+        assert!(tile(&wiki, name, "Unused").is_some());
+        assert_eq!(pixel(&wiki, name, 16, old), RED);
+
+        let options = Options {
+            force_deletions: true,
+            ..options
+        };
+        update_tilesheet(wiki.clone(), &base, name, options).unwrap();
+        let renamed = tile(&wiki, name, "New Ingot").unwrap();
+        assert_eq!((renamed.id, renamed.pos), (id, old));
+        assert!(tile(&wiki, name, "Old Ingot").is_none());
+        assert!(tile(&wiki, name, "Unused").is_none());
+        let added = tile(&wiki, name, "Copper Ingot").unwrap();
+        assert_ne!(added.pos, old);
+        assert_eq!(pixel(&wiki, name, 16, old), BLUE);
+        assert_eq!(pixel(&wiki, name, 16, added.pos), YELLOW);
+        assert_eq!(wiki.sizes(name), Some(vec![16]));
+        assert_eq!(fs::read_to_string(&todelete).unwrap(), "");
+    }
+    #[test]
+    fn translations_are_set_on_tiles() {
+        let name = "Test Translate";
+        let base = Base::new(name);
+        let lang = base.join(name).join("assets/test/lang");
+        create_dir_all(&lang).unwrap();
+        let english = r#"{"item.test.iron": "Iron Ingot", "item.test.gold": "Gold Ingot"}"#;
+        fs::write(lang.join("en_us.json"), english).unwrap();
+        let german = r#"{
+            "item.test.iron": "Eisenbarren",
+            "item.test.iron.desc": "Aus Eisenerz",
+            "item.test.gold": "Gold Ingot"
+        }"#;
+        fs::write(lang.join("de_de.json"), german).unwrap();
+        let pos = TilePos { x: 0, y: 0, z: 0 };
+        let wiki = MemoryWiki::new()
+            .with_sheet(name, &[16])
+            .with_tile(name, "Iron Ingot", pos)
+            .with_tile(name, "Gold Ingot", TilePos { x: 1, y: 0, z: 0 });
+        let options = Options {
+            yes: true,
+            ..Options::default()
+        };
+        sync_translations(wiki.clone(), &base, name, options).unwrap();
+        let iron = tile(&wiki, name, "Iron Ingot").unwrap().id;
+        let gold = tile(&wiki, name, "Gold Ingot").unwrap().id;
+        let expected = ("Eisenbarren".to_owned(), "Aus Eisenerz".to_owned());
+        assert_eq!(wiki.translation(iron, "de"), Some(expected));
+        // Translations that only repeat the English name are left out.
+        assert_eq!(wiki.translation(gold, "de"), None);
+
+        // Locales without a description keep the one already on the wiki.
+        let german = r#"{"item.test.iron": "Eisen"}"#;
+        fs::write(lang.join("de_de.json"), german).unwrap();
+        let options = Options {
+            yes: true,
+            ..Options::default()
+        };
+        sync_translations(wiki.clone(), &base, name, options).unwrap();
+        let expected = ("Eisen".to_owned(), "Aus Eisenerz".to_owned());
+        assert_eq!(wiki.translation(iron, "de"), Some(expected));
+    }
+    #[test]
+    fn dry_run_is_refused_while_an_update_is_unfinished() {
+        let name = "Test Pending";
+        let base = Base::new(name);
+        write_tile(&base, name, "Iron Ingot", RED);
+        let layer = format!("Tilesheet {name} 16 0.png");
+        fs::write(base.join(&layer), "pending").unwrap();
+        let journal = Journal {
+            layers: vec![layer.clone()],
+            ..Journal::default()
+        };
+        journal.save(&base, name).unwrap();
+        let options = Options {
+            dry_run: true,
+            yes: true,
+            sizes: Some(vec![16]),
+            ..Options::default()
+        };
+        let result = update_tilesheet(MemoryWiki::new(), &base, name, options);
+        assert!(matches!(result, Err(Error::Invalid(_))));
+        assert_eq!(fs::read_to_string(base.join(&layer)).unwrap(), "pending");
+    }
+    #[test]
+    fn grouped_tiles_are_placed_together() {
+        let name = "Test Grouping";
+        let base = Base::new(name);
+        let mut layer = RgbaImage::new(64, 16);
+        imageops::replace(&mut layer, &RgbaImage::from_pixel(16, 16, RED), 0, 0);
+        imageops::replace(&mut layer, &RgbaImage::from_pixel(16, 16, GREEN), 32, 0);
+        let wiki = MemoryWiki::new()
+            .with_sheet(name, &[16])
+            .with_file(&format!("Tilesheet {name} 16 0.png"), png(&layer))
+            .with_tile(name, "Stone", TilePos { x: 0, y: 0, z: 0 })
+            .with_tile(name, "Dirt", TilePos { x: 2, y: 0, z: 0 });
+        write_tile(&base, name, "Stone", RED);
+        write_tile(&base, name, "Dirt", GREEN);
+        write_tile(&base, name, "Apple Pie", BLUE);
+        write_tile(&base, name, "Apple Juice", YELLOW);
+        write_tile(&base, name, "Zinc Ingot", BLUE);
+        let options = Options {
+            yes: true,
+            allow_upload_warnings: true,
+            placement: Placement {
+                layout: Layout::Rows(4),
+                grouping: Grouping::Prefix,
+                ..Placement::default()
+            },
+            ..Options::default()
+        };
+        update_tilesheet(wiki.clone(), &base, name, options).unwrap();
+        // The hole between the old tiles only fits one tile, so the apples go after them.
+        let pos = |tile_name| tile(&wiki, name, tile_name).unwrap().pos;
+        assert_eq!(pos("Apple Juice"), TilePos { x: 3, y: 0, z: 0 });
+        assert_eq!(pos("Apple Pie"), TilePos { x: 0, y: 1, z: 0 });
+        assert_eq!(pos("Zinc Ingot"), TilePos { x: 1, y: 0, z: 0 });
+        assert_eq!(pixel(&wiki, name, 16, pos("Apple Pie")), BLUE);
+    }
+    #[test]
+    fn repack_moves_tiles_and_drops_empty_layers() {
+        let name = "Test Repack";
+        let base = Base::new(name);
+        let layer = |color| {
+            let mut layer = RgbaImage::new(32, 32);
+            imageops::replace(&mut layer, &RgbaImage::from_pixel(16, 16, color), 0, 0);
+            png(&layer)
+        };
+        let first = TilePos { x: 0, y: 0, z: 0 };
+        let last = TilePos { x: 0, y: 0, z: 1 };
+        let wiki = MemoryWiki::new()
+            .with_sheet(name, &[16])
+            .with_file(&format!("Tilesheet {name} 16 0.png"), layer(RED))
+            .with_file(&format!("Tilesheet {name} 16 1.png"), layer(GREEN))
+            .with_tile(name, "Iron Ingot", first)
+            .with_tile(name, "Gold Ingot", last);
+        let id = tile(&wiki, name, "Gold Ingot").unwrap().id;
+        let options = Options {
+            yes: true,
+            allow_upload_warnings: true,
+            placement: Placement {
+                max_size: 2,
+                ..Placement::default()
+            },
+            ..Options::default()
+        };
+        repack_tilesheet(wiki.clone(), &base, name, options).unwrap();
+        let moved = tile(&wiki, name, "Gold Ingot").unwrap();
+        assert_eq!(moved.id, id);
+        assert_eq!(moved.pos, TilePos { x: 0, y: 1, z: 0 });
+        assert_eq!(tile(&wiki, name, "Iron Ingot").unwrap().pos, first);
+        assert_eq!(pixel(&wiki, name, 16, moved.pos), GREEN);
+        assert_eq!(pixel(&wiki, name, 16, first), RED);
+        assert!(wiki.file(&format!("Tilesheet {name} 16 1.png")).is_none());
+    }
+    /// A tilesheet with one red tile at each of these sizes.
+    fn iron_sheet(name: &str, sizes: &[u32]) -> MemoryWiki {
+        let mut wiki = MemoryWiki::new().with_sheet(name, sizes);
+        for &size in sizes {
+            let layer = RgbaImage::from_pixel(size, size, RED);
+            wiki = wiki.with_file(&format!("Tilesheet {name} {size} 0.png"), png(&layer));
+        }
+        wiki.with_tile(name, "Iron Ingot", TilePos { x: 0, y: 0, z: 0 })
+    }
+    #[test]
+    fn sizes_are_added_from_local_files() {
+        let name = "Test Add Size";
+        let base = Base::new(name);
+        let wiki = iron_sheet(name, &[16]);
+        write_tile(&base, name, "Iron Ingot", BLUE);
+        let options = Options {
+            yes: true,
+            ..Options::default()
+        };
+        change_sizes(wiki.clone(), &base, name, &[32], &[], options).unwrap();
+        assert_eq!(wiki.sizes(name), Some(vec![16, 32]));
+        let pos = tile(&wiki, name, "Iron Ingot").unwrap().pos;
+        assert_eq!(pixel(&wiki, name, 32, pos), BLUE);
+        assert_eq!(pixel(&wiki, name, 16, pos), RED);
+    }
+    #[test]
+    fn sizes_are_added_from_the_largest_layer() {
+        let name = "Test Add Size Without Files";
+        let base = Base::new(name);
+        let wiki = iron_sheet(name, &[16, 32]);
+        let options = Options {
+            yes: true,
+            ..Options::default()
+        };
+        change_sizes(wiki.clone(), &base, name, &[64], &[], options).unwrap();
+        assert_eq!(wiki.sizes(name), Some(vec![16, 32, 64]));
+        let pos = tile(&wiki, name, "Iron Ingot").unwrap().pos;
+        assert_eq!(pixel(&wiki, name, 64, pos), RED);
+    }
+    #[test]
+    fn sizes_are_removed() {
+        let name = "Test Remove Size";
+        let base = Base::new(name);
+        let wiki = iron_sheet(name, &[16, 32]);
+        let options = Options {
+            yes: true,
+            ..Options::default()
+        };
+        change_sizes(wiki.clone(), &base, name, &[], &[16], options.clone()).unwrap();
+        assert_eq!(wiki.sizes(name), Some(vec![32]));
+        // The images of the removed size are only listed as unused.
+        assert!(wiki.file(&format!("Tilesheet {name} 16 0.png")).is_some());
+        assert!(tile(&wiki, name, "Iron Ingot").is_some());
+        let result = change_sizes(wiki.clone(), &base, name, &[], &[32], options);
+        assert!(matches!(result, Err(Error::Invalid(_))));
+    }
+    #[test]
+    fn malformed_records_of_other_mods_are_skipped() {
+        let name = "Test Malformed";
+        let base = Base::new(name);
+        let wiki =
+            iron_sheet(name, &[16]).with_malformed_sheet(r#"{"mod": "Other", "sizes": "16"}"#);
+        write_tile(&base, name, "Iron Ingot", BLUE);
+        let options = Options {
+            yes: true,
+            allow_upload_warnings: true,
+            sizes: Some(vec![16]),
+            ..Options::default()
+        };
+        update_tilesheet(wiki.clone(), &base, name, options.clone()).unwrap();
+        let pos = tile(&wiki, name, "Iron Ingot").unwrap().pos;
+        assert_eq!(pixel(&wiki, name, 16, pos), BLUE);
+
+        // Without a valid record the malformed one might be this mod's, so nothing is created.
+        let other = "Test Malformed Missing";
+        let other_base = Base::new(other);
+        write_tile(&other_base, other, "Iron Ingot", BLUE);
+        let result = update_tilesheet(wiki.clone(), &other_base, other, options);
+        assert!(matches!(result, Err(Error::Response(_))));
+        assert_eq!(wiki.sizes(other), None);
+    }
+    #[test]
+    fn resume_finishes_an_interrupted_update() {
+        let name = "Test Resume";
+        let base = Base::new(name);
+        write_tile(&base, name, "Iron Ingot", RED);
+        write_tile(&base, name, "Gold Ingot", GREEN);
+        let wiki = MemoryWiki::new().with_lost_adds(1);
+        let options = Options {
+            yes: true,
+            sizes: Some(vec![16]),
+            ..Options::default()
+        };
+        let result = update_tilesheet(wiki.clone(), &base, name, options.clone());
+        assert!(matches!(result, Err(Error::Response(_))));
+        assert!(Journal::load(&base, name).unwrap().is_some());
+        let result = update_tilesheet(wiki.clone(), &base, name, options.clone());
+        assert!(matches!(result, Err(Error::Invalid(_))));
+
+        resume_update(wiki.clone(), &base, name, options).unwrap();
+        let mut names = wiki
+            .tiles(name)
+            .into_iter()
+            .map(|tile| tile.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["Gold Ingot", "Iron Ingot"]);
+        assert!(Journal::load(&base, name).unwrap().is_none());
+    }
+    #[test]
+    fn recoloured_tiles_are_not_reported_as_duplicates() {
+        let name = "Test Duplicates";
+        let base = Base::new(name);
+        let colors = [
+            ("Copper Ingot", [230, 120, 40]),
+            ("Iron Ingot", [200, 200, 200]),
+            ("Gold Ingot", [250, 210, 40]),
+            ("Gold Ingot Copy", [250, 210, 40]),
+        ];
+        for (file, [r, g, b]) in colors {
+            let img = RgbaImage::from_fn(16, 16, |x, y| {
+                let shade = 80 + 175 * ((x * 7 + y * 3) % 16) / 15;
+                Rgba([
+                    (r * shade / 255) as u8,
+                    (g * shade / 255) as u8,
+                    (b * shade / 255) as u8,
+                    255,
+                ])
+            });
+            img.save(base.join(name).join(format!("{file}.png")))
+                .unwrap();
+        }
+        find_duplicates(MemoryWiki::new(), &base, name, Options::default()).unwrap();
+        let report = fs::read_to_string(base.join("duplicates.txt")).unwrap();
+        assert_eq!(report, "Identical: Gold Ingot, Gold Ingot Copy\n");
+    }
+    #[test]
+    fn diff_suggests_renames_and_clears_old_ones() {
+        let name = "Test Diff";
+        let base = Base::new(name);
+        let layer = RgbaImage::from_pixel(16, 16, RED);
+        let wiki = MemoryWiki::new()
+            .with_sheet(name, &[16])
+            .with_file(&format!("Tilesheet {name} 16 0.png"), png(&layer))
+            .with_tile(name, "Old Ingot", TilePos { x: 0, y: 0, z: 0 });
+        let suggestions = base.join("suggested_renames.txt");
+        fs::write(&suggestions, "Stale => Suggestion\n").unwrap();
+        write_tile(&base, name, "New Ingot", RED);
+        diff_tilesheet(wiki.clone(), &base, name, Options::default()).unwrap();
+        let lines = fs::read_to_string(&suggestions).unwrap();
+        assert_eq!(lines, "# 100% similar\nOld Ingot => New Ingot\n");
+
+        fs::write(
+            base.join(name).join("renames.txt"),
+            "Old Ingot => New Ingot\n",
+        )
+        .unwrap();
+        diff_tilesheet(wiki, &base, name, Options::default()).unwrap();
+        assert_eq!(fs::read_to_string(&suggestions).unwrap(), "");
+    }
 }
@@ -0,0 +1,193 @@
+use crate::{
+    decode_srgb,
+    error::{Error, Result},
+    FloatImage,
+};
+use image::{imageops, Rgba, RgbaImage};
+use serde::Deserialize;
+
+/// Which frame of an animated texture becomes the tile.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Frame {
+    /// The frame the animation starts on.
+    #[default]
+    First,
+    /// The frame at this index in the strip, counting from the top.
+    Index(u32),
+    /// Every frame of the animation blended together, weighted by how long each is shown.
+    Blend,
+}
+impl Frame {
+    /// Parses `first`, `blend` or a frame index.
+    pub fn parse(frame: &str) -> Option<Frame> {
+        match frame {
+            "first" => Some(Frame::First),
+            "blend" => Some(Frame::Blend),
+            _ => frame.parse().ok().map(Frame::Index),
+        }
+    }
+}
+#[derive(Debug, Deserialize)]
+struct Meta {
+    animation: Option<Animation>,
+}
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FrameMeta {
+    Index(u32),
+    Timed { index: u32, time: Option<u32> },
+}
+/// The `animation` section of a `.png.mcmeta` sidecar.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct Animation {
+    frametime: Option<u32>,
+    frames: Option<Vec<FrameMeta>>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+/// Parses a `.png.mcmeta` sidecar, returning its animation if it has one.
+pub(super) fn parse_mcmeta(data: &[u8]) -> serde_json::Result<Option<Animation>> {
+    let meta: Meta = serde_json::from_slice(data)?;
+    Ok(meta.animation)
+}
+/// Picks the frame of a texture that should become the tile. Textures with an animation sidecar
+/// are cut into frames, and so are those without one that are a vertical strip of square frames
+/// when `strips` is set. Other tall textures, such as doors, are left for fitting.
+pub(super) fn select_frame(
+    img: &RgbaImage,
+    animation: Option<&Animation>,
+    frame: Frame,
+    strips: bool,
+    origin: &str,
+) -> Result<FloatImage> {
+    let (w, h) = img.dimensions();
+    let strip = Animation::default();
+    let animation = match animation {
+        Some(animation) => animation,
+        None if strips && w > 0 && h > w && h % w == 0 => &strip,
+        None => return Ok(decode_srgb(img)),
+    };
+    let frame_height = match (animation.width, animation.height) {
+        (Some(width), Some(height)) if width > 0 => w * height / width,
+        _ => w,
+    };
+    if frame_height == 0 || h % frame_height != 0 {
+        return Err(Error::Invalid(format!(
+            "{origin} is {w}x{h} which is not a strip of {w}x{frame_height} frames"
+        )));
+    }
+    let count = h / frame_height;
+    let frametime = animation.frametime.unwrap_or(1);
+    let sequence: Vec<(u32, u32)> = match &animation.frames {
+        Some(frames) if !frames.is_empty() => frames
+            .iter()
+            .map(|frame| match *frame {
+                FrameMeta::Index(index) => (index, frametime),
+                FrameMeta::Timed { index, time } => (index, time.unwrap_or(frametime)),
+            })
+            .collect(),
+        _ => (0..count).map(|index| (index, frametime)).collect(),
+    };
+    let crop = |index: u32| {
+        if index >= count {
+            return Err(Error::Invalid(format!(
+                "{origin} has {count} frames but frame {index} was requested"
+            )));
+        }
+        let frame = imageops::crop_imm(img, 0, index * frame_height, w, frame_height);
+        Ok(decode_srgb(&frame.to_image()))
+    };
+    match frame {
+        Frame::First => crop(sequence[0].0),
+        Frame::Index(index) => crop(index),
+        Frame::Blend => {
+            let mut blended = FloatImage::new(w, frame_height);
+            let total = sequence.iter().map(|&(_, time)| time).sum::<u32>().max(1) as f32;
+            for &(index, time) in &sequence {
+                let frame = crop(index)?;
+                let weight = time as f32 / total;
+                for (p, q) in blended.pixels_mut().zip(frame.pixels()) {
+                    *p = Rgba([
+                        p[0] + q[0] * weight,
+                        p[1] + q[1] * weight,
+                        p[2] + q[2] * weight,
+                        p[3] + q[3] * weight,
+                    ]);
+                }
+            }
+            Ok(blended)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A strip of 2x2 frames, each filled with its index as the red channel.
+    fn strip(frames: u32) -> RgbaImage {
+        RgbaImage::from_fn(2, 2 * frames, |_, y| Rgba([(y / 2) as u8 * 50, 0, 0, 255]))
+    }
+    fn red(img: &FloatImage) -> u8 {
+        crate::encode_srgb(img)[(0, 0)][0]
+    }
+    #[test]
+    fn parse_frames() {
+        assert_eq!(Frame::parse("first"), Some(Frame::First));
+        assert_eq!(Frame::parse("blend"), Some(Frame::Blend));
+        assert_eq!(Frame::parse("3"), Some(Frame::Index(3)));
+        assert_eq!(Frame::parse("last"), None);
+    }
+    #[test]
+    fn parse_sidecars() {
+        let meta = br#"{"animation": {"frametime": 4, "frames": [2, {"index": 0, "time": 8}]}}"#;
+        let animation = parse_mcmeta(meta).unwrap().unwrap();
+        assert_eq!(animation.frametime, Some(4));
+        assert_eq!(animation.frames.unwrap().len(), 2);
+        assert!(parse_mcmeta(br#"{"texture": {"blur": true}}"#)
+            .unwrap()
+            .is_none());
+        assert!(parse_mcmeta(b"not json").is_err());
+    }
+    #[test]
+    fn select_frames() {
+        let img = strip(3);
+        let animation = parse_mcmeta(br#"{"animation": {"frames": [2, 1]}}"#)
+            .unwrap()
+            .unwrap();
+        let first = select_frame(&img, Some(&animation), Frame::First, false, "a").unwrap();
+        assert_eq!(first.dimensions(), (2, 2));
+        assert_eq!(red(&first), 100);
+        let index = select_frame(&img, Some(&animation), Frame::Index(1), false, "a").unwrap();
+        assert_eq!(red(&index), 50);
+        assert!(select_frame(&img, Some(&animation), Frame::Index(3), false, "a").is_err());
+    }
+    #[test]
+    fn tall_textures_without_sidecar_are_kept_whole() {
+        let img = strip(2);
+        let whole = select_frame(&img, None, Frame::First, false, "door").unwrap();
+        assert_eq!(whole.dimensions(), (2, 4));
+    }
+    #[test]
+    fn strips_without_sidecar_are_cut_when_asked() {
+        let img = strip(3);
+        let last = select_frame(&img, None, Frame::Index(2), true, "a").unwrap();
+        assert_eq!(last.dimensions(), (2, 2));
+        assert_eq!(red(&last), 100);
+        let blended = select_frame(&img, None, Frame::Blend, true, "a").unwrap();
+        assert_eq!(blended.dimensions(), (2, 2));
+        // Textures that are not a strip of square frames are still kept whole.
+        let odd = RgbaImage::new(2, 5);
+        let whole = select_frame(&odd, None, Frame::First, true, "a").unwrap();
+        assert_eq!(whole.dimensions(), (2, 5));
+    }
+    #[test]
+    fn blend_weights_frames_by_time() {
+        let img = RgbaImage::from_fn(1, 2, |_, y| Rgba([0, 0, 0, if y == 0 { 255 } else { 0 }]));
+        let animation = parse_mcmeta(br#"{"animation": {"frames": [{"index": 0, "time": 3}, 1]}}"#)
+            .unwrap()
+            .unwrap();
+        let blended = select_frame(&img, Some(&animation), Frame::Blend, false, "a").unwrap();
+        assert!((blended[(0, 0)][3] - 0.75).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,513 @@
+use super::TilePos;
+use crate::error::{Error, Result};
+use mediawiki::{tilesheet::Tilesheet, Error as MwError, Mediawiki, Upload};
+use serde_json::Value as Json;
+use std::path::Path;
+#[cfg(test)]
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fs,
+    rc::Rc,
+};
+
+/// A registered tilesheet and the sizes it has images for.
+#[derive(Clone, Debug)]
+pub struct SheetInfo {
+    pub name: String,
+    pub sizes: Vec<u32>,
+}
+/// A tile registered on the wiki.
+#[derive(Clone, Debug)]
+pub struct TileInfo {
+    pub id: u64,
+    pub name: String,
+    pub pos: TilePos,
+}
+pub enum UploadResult {
+    Success,
+    NoChange,
+    Warning {
+        filekey: String,
+        warnings: Vec<(String, String)>,
+    },
+}
+/// Everything the tilesheet manager needs from the wiki.
+pub trait Backend {
+    fn query_sheets(&self) -> Vec<Result<SheetInfo>>;
+    fn query_tiles(&self, tsmod: &str) -> Vec<Result<TileInfo>>;
+    fn download_file(&self, name: &str) -> Result<Option<Vec<u8>>>;
+    fn create_sheet(&self, tsmod: &str, sizes: &[u32]) -> Result<()>;
+    /// Replaces the list of sizes of an existing tilesheet.
+    fn edit_sheet(&self, tsmod: &str, sizes: &[u32]) -> Result<()>;
+    /// Uploads a file without ignoring warnings.
+    fn upload(&self, name: &str, path: &Path) -> Result<UploadResult>;
+    /// Finishes an upload that returned warnings, ignoring them.
+    fn upload_filekey(&self, name: &str, filekey: &str) -> Result<UploadResult>;
+    /// Changes the name and/or position of a registered tile, keeping its id.
+    fn edit_tile(&self, id: u64, name: Option<&str>, pos: Option<TilePos>) -> Result<()>;
+    fn delete_tiles(&self, ids: &[u64]) -> Result<()>;
+    /// Deletes a file, such as a tilesheet image that is no longer used.
+    fn delete_file(&self, name: &str) -> Result<()>;
+    /// Finds the pages whose source mentions a tile name along with its mod abbreviation.
+    fn tile_usage(&self, tsmod: &str, name: &str) -> Result<Vec<String>>;
+    /// Sets the display name of a tile in a language, and its description if one is given. A
+    /// description that is left out keeps its earlier translation.
+    fn translate_tile(
+        &self,
+        id: u64,
+        lang: &str,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<()>;
+    fn add_tiles(&self, tsmod: &str, tiles: &[(&str, TilePos)]) -> Result<()>;
+}
+
+const UPLOAD_TEXT: &str = "[[Category:Tilesheets]]";
+const UPLOAD_COMMENT: &str = "Tilesheet uploaded by ftb-rs";
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json> {
+    match json.get(key) {
+        Some(value) => Ok(value),
+        None => Err(Error::Response(format!("missing field {key:?} in {json}"))),
+    }
+}
+fn field_u64(json: &Json, key: &str) -> Result<u64> {
+    field(json, key)?
+        .as_u64()
+        .ok_or_else(|| Error::Response(format!("field {key:?} is not a number in {json}")))
+}
+fn field_str<'a>(json: &'a Json, key: &str) -> Result<&'a str> {
+    field(json, key)?
+        .as_str()
+        .ok_or_else(|| Error::Response(format!("field {key:?} is not a string in {json}")))
+}
+fn upload_result(result: Json) -> Result<UploadResult> {
+    let upload = field(&result, "upload")?;
+    match field_str(upload, "result")? {
+        "Warning" => {
+            let warnings = field(upload, "warnings")?
+                .as_object()
+                .ok_or_else(|| Error::Response(format!("invalid upload warnings in {result}")))?
+                .iter()
+                .map(|(warning, value)| (warning.clone(), value.to_string()))
+                .collect();
+            let filekey = field_str(upload, "filekey")?.to_owned();
+            Ok(UploadResult::Warning { filekey, warnings })
+        }
+        "Success" => Ok(UploadResult::Success),
+        other => Err(Error::Response(format!("unknown upload result {other:?}"))),
+    }
+}
+fn upload_error(err: MwError) -> Result<UploadResult> {
+    match err {
+        MwError::Json(err) if err["error"]["code"].as_str() == Some("fileexists-no-change") => {
+            Ok(UploadResult::NoChange)
+        }
+        e => Err(e.into()),
+    }
+}
+impl Backend for Mediawiki {
+    fn query_sheets(&self) -> Vec<Result<SheetInfo>> {
+        Tilesheet::query_sheets(self)
+            .into_iter()
+            .map(|sheet| {
+                let sheet = sheet?;
+                let name = field_str(&sheet, "mod")?.to_owned();
+                let sizes = field(&sheet, "sizes")?
+                    .as_array()
+                    .ok_or_else(|| Error::Response(format!("invalid sizes in {sheet}")))?
+                    .iter()
+                    .map(|x| {
+                        x.as_u64()
+                            .map(|x| x as u32)
+                            .ok_or_else(|| Error::Response(format!("invalid size in {sheet}")))
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(SheetInfo { name, sizes })
+            })
+            .collect()
+    }
+    fn query_tiles(&self, tsmod: &str) -> Vec<Result<TileInfo>> {
+        Tilesheet::query_tiles(self, Some(tsmod))
+            .into_iter()
+            .map(|tile| {
+                let tile = tile?;
+                let x = field_u64(&tile, "x")? as u32;
+                let y = field_u64(&tile, "y")? as u32;
+                let z = field_u64(&tile, "z")? as u32;
+                let id = field_u64(&tile, "id")?;
+                let name = field_str(&tile, "name")?.to_owned();
+                Ok(TileInfo {
+                    id,
+                    name,
+                    pos: TilePos { x, y, z },
+                })
+            })
+            .collect()
+    }
+    fn download_file(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(Mediawiki::download_file(self, name)?)
+    }
+    fn create_sheet(&self, tsmod: &str, sizes: &[u32]) -> Result<()> {
+        let token = self.get_token()?;
+        let sizes = sizes
+            .iter()
+            .map(|size| size.to_string())
+            .collect::<Vec<_>>()
+            .join("|");
+        Tilesheet::create_sheet(self, &token, tsmod, &sizes, "Tilesheet created by ftb-rs")?;
+        Ok(())
+    }
+    fn edit_sheet(&self, tsmod: &str, sizes: &[u32]) -> Result<()> {
+        let token = self.get_token()?;
+        let sizes = sizes
+            .iter()
+            .map(|size| size.to_string())
+            .collect::<Vec<_>>()
+            .join("|");
+        Tilesheet::edit_sheet(
+            self,
+            &token,
+            tsmod,
+            None,
+            Some(&sizes),
+            Some("ftb-rs editing tilesheet sizes"),
+        )?;
+        Ok(())
+    }
+    fn upload(&self, name: &str, path: &Path) -> Result<UploadResult> {
+        let token = self.get_token()?;
+        match Mediawiki::upload(
+            self,
+            name,
+            &token,
+            Upload::File(path),
+            Some(UPLOAD_TEXT),
+            Some(UPLOAD_COMMENT),
+            false,
+        ) {
+            Ok(result) => upload_result(result),
+            Err(e) => upload_error(e),
+        }
+    }
+    fn upload_filekey(&self, name: &str, filekey: &str) -> Result<UploadResult> {
+        let token = self.get_token()?;
+        match Mediawiki::upload(
+            self,
+            name,
+            &token,
+            Upload::Filekey(filekey),
+            Some(UPLOAD_TEXT),
+            Some(UPLOAD_COMMENT),
+            true,
+        ) {
+            Ok(result) => upload_result(result),
+            Err(e) => upload_error(e),
+        }
+    }
+    fn edit_tile(&self, id: u64, name: Option<&str>, pos: Option<TilePos>) -> Result<()> {
+        let token = self.get_token()?;
+        Tilesheet::edit_tile(
+            self,
+            &token,
+            id,
+            name,
+            None,
+            pos.map(|pos| pos.x),
+            pos.map(|pos| pos.y),
+            pos.map(|pos| pos.z),
+            Some("ftb-rs editing tile"),
+        )?;
+        Ok(())
+    }
+    fn delete_tiles(&self, ids: &[u64]) -> Result<()> {
+        let token = self.get_token()?;
+        let tiles = ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join("|");
+        Tilesheet::delete_tiles(self, &token, &tiles, Some("ftb-rs deleting tiles"))?;
+        Ok(())
+    }
+    fn delete_file(&self, name: &str) -> Result<()> {
+        let token = self.get_token()?;
+        let title = format!("File:{name}");
+        Mediawiki::post_request(
+            self,
+            &[
+                ("action", "delete"),
+                ("title", &title),
+                ("reason", "ftb-rs deleting unused tilesheet image"),
+                ("token", &token),
+            ],
+        )?;
+        Ok(())
+    }
+    fn tile_usage(&self, tsmod: &str, name: &str) -> Result<Vec<String>> {
+        let search = format!(
+            "insource:\"{}\" insource:\"{}\"",
+            name.replace('"', " "),
+            tsmod.replace('"', " ")
+        );
+        Mediawiki::query_search(self, &search)
+            .into_iter()
+            .map(|page| Ok(field_str(&page?, "title")?.to_owned()))
+            .collect()
+    }
+    fn translate_tile(
+        &self,
+        id: u64,
+        lang: &str,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<()> {
+        let token = self.get_token()?;
+        Tilesheet::translate_tile(
+            self,
+            &token,
+            id,
+            lang,
+            Some(name),
+            description,
+            Some("ftb-rs translating tile"),
+        )?;
+        Ok(())
+    }
+    fn add_tiles(&self, tsmod: &str, tiles: &[(&str, TilePos)]) -> Result<()> {
+        let token = self.get_token()?;
+        let tiles = tiles
+            .iter()
+            .map(|(name, pos)| format!("{} {} {} {}", pos.x, pos.y, pos.z, name))
+            .collect::<Vec<_>>()
+            .join("|");
+        Tilesheet::add_tiles(self, &token, tsmod, &tiles, Some("ftb-rs adding tiles"))?;
+        Ok(())
+    }
+}
+
+/// Translated names and descriptions by tile id and language.
+#[cfg(test)]
+type Translations = HashMap<(u64, String), (String, String)>;
+/// An in-memory stand-in for the wiki, for running the tilesheet pipeline offline against
+/// fixture sheets and tile lists. Clones share their state, so a test can hand one to the
+/// manager and inspect the wiki afterwards through another.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MemoryWiki {
+    sheets: Rc<RefCell<Vec<SheetInfo>>>,
+    files: Rc<RefCell<HashMap<String, Vec<u8>>>>,
+    stash: Rc<RefCell<HashMap<String, Vec<u8>>>>,
+    tiles: Rc<RefCell<Vec<(String, TileInfo)>>>,
+    /// Page sources by title.
+    pages: Rc<RefCell<HashMap<String, String>>>,
+    translations: Rc<RefCell<Translations>>,
+    next_id: Rc<Cell<u64>>,
+    /// How many more calls to add tiles lose their answer after the tiles were added.
+    lost_adds: Rc<Cell<u32>>,
+    /// Tilesheet records that cannot be parsed.
+    malformed: Rc<RefCell<Vec<String>>>,
+}
+#[cfg(test)]
+impl MemoryWiki {
+    pub fn new() -> MemoryWiki {
+        MemoryWiki::default()
+    }
+    pub fn with_sheet(self, tsmod: &str, sizes: &[u32]) -> MemoryWiki {
+        self.sheets.borrow_mut().push(SheetInfo {
+            name: tsmod.to_owned(),
+            sizes: sizes.to_vec(),
+        });
+        self
+    }
+    pub fn with_file(self, name: &str, data: Vec<u8>) -> MemoryWiki {
+        self.files.borrow_mut().insert(name.to_owned(), data);
+        self
+    }
+    pub fn with_tile(self, tsmod: &str, name: &str, pos: TilePos) -> MemoryWiki {
+        self.insert_tile(tsmod, name, pos);
+        self
+    }
+    /// Makes the next calls to add tiles fail after adding them, as when the connection drops
+    /// before the wiki answers.
+    pub fn with_lost_adds(self, count: u32) -> MemoryWiki {
+        self.lost_adds.set(count);
+        self
+    }
+    pub fn with_malformed_sheet(self, record: &str) -> MemoryWiki {
+        self.malformed.borrow_mut().push(record.to_owned());
+        self
+    }
+    pub fn with_page(self, title: &str, text: &str) -> MemoryWiki {
+        self.pages
+            .borrow_mut()
+            .insert(title.to_owned(), text.to_owned());
+        self
+    }
+    pub fn file(&self, name: &str) -> Option<Vec<u8>> {
+        self.files.borrow().get(name).cloned()
+    }
+    pub fn sizes(&self, tsmod: &str) -> Option<Vec<u32>> {
+        self.sheets
+            .borrow()
+            .iter()
+            .find(|sheet| sheet.name == tsmod)
+            .map(|sheet| sheet.sizes.clone())
+    }
+    pub fn tiles(&self, tsmod: &str) -> Vec<TileInfo> {
+        self.tiles
+            .borrow()
+            .iter()
+            .filter(|(m, _)| m == tsmod)
+            .map(|(_, tile)| tile.clone())
+            .collect()
+    }
+    pub fn translation(&self, id: u64, lang: &str) -> Option<(String, String)> {
+        self.translations
+            .borrow()
+            .get(&(id, lang.to_owned()))
+            .cloned()
+    }
+    fn insert_tile(&self, tsmod: &str, name: &str, pos: TilePos) {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        let tile = TileInfo {
+            id,
+            name: name.to_owned(),
+            pos,
+        };
+        self.tiles.borrow_mut().push((tsmod.to_owned(), tile));
+    }
+}
+#[cfg(test)]
+impl Backend for MemoryWiki {
+    fn query_sheets(&self) -> Vec<Result<SheetInfo>> {
+        let malformed = self.malformed.borrow();
+        let malformed = malformed
+            .iter()
+            .map(|record| Err(Error::Response(format!("invalid sizes in {record}"))));
+        self.sheets
+            .borrow()
+            .iter()
+            .cloned()
+            .map(Ok)
+            .chain(malformed)
+            .collect()
+    }
+    fn query_tiles(&self, tsmod: &str) -> Vec<Result<TileInfo>> {
+        self.tiles(tsmod).into_iter().map(Ok).collect()
+    }
+    fn download_file(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.file(name))
+    }
+    fn create_sheet(&self, tsmod: &str, sizes: &[u32]) -> Result<()> {
+        self.sheets.borrow_mut().push(SheetInfo {
+            name: tsmod.to_owned(),
+            sizes: sizes.to_vec(),
+        });
+        Ok(())
+    }
+    fn edit_sheet(&self, tsmod: &str, sizes: &[u32]) -> Result<()> {
+        let mut sheets = self.sheets.borrow_mut();
+        let sheet = sheets
+            .iter_mut()
+            .find(|sheet| sheet.name == tsmod)
+            .ok_or_else(|| Error::Response(format!("no tilesheet for {tsmod:?}")))?;
+        sheet.sizes = sizes.to_vec();
+        Ok(())
+    }
+    fn upload(&self, name: &str, path: &Path) -> Result<UploadResult> {
+        let data = fs::read(path).map_err(Error::io(path))?;
+        let mut files = self.files.borrow_mut();
+        match files.get(name) {
+            Some(old) if *old == data => Ok(UploadResult::NoChange),
+            Some(_) => {
+                self.stash.borrow_mut().insert(name.to_owned(), data);
+                Ok(UploadResult::Warning {
+                    filekey: name.to_owned(),
+                    warnings: vec![("exists".to_owned(), format!("{name:?}"))],
+                })
+            }
+            None => {
+                files.insert(name.to_owned(), data);
+                Ok(UploadResult::Success)
+            }
+        }
+    }
+    fn upload_filekey(&self, name: &str, filekey: &str) -> Result<UploadResult> {
+        let data = self
+            .stash
+            .borrow_mut()
+            .remove(filekey)
+            .ok_or_else(|| Error::Response(format!("unknown filekey {filekey:?}")))?;
+        self.files.borrow_mut().insert(name.to_owned(), data);
+        Ok(UploadResult::Success)
+    }
+    fn edit_tile(&self, id: u64, name: Option<&str>, pos: Option<TilePos>) -> Result<()> {
+        let mut tiles = self.tiles.borrow_mut();
+        let (_, tile) = tiles
+            .iter_mut()
+            .find(|(_, tile)| tile.id == id)
+            .ok_or_else(|| Error::Response(format!("no tile with id {id}")))?;
+        if let Some(name) = name {
+            tile.name = name.to_owned();
+        }
+        if let Some(pos) = pos {
+            tile.pos = pos;
+        }
+        Ok(())
+    }
+    fn delete_tiles(&self, ids: &[u64]) -> Result<()> {
+        self.tiles
+            .borrow_mut()
+            .retain(|(_, tile)| !ids.contains(&tile.id));
+        Ok(())
+    }
+    fn delete_file(&self, name: &str) -> Result<()> {
+        self.files
+            .borrow_mut()
+            .remove(name)
+            .map(drop)
+            .ok_or_else(|| Error::Response(format!("no file named {name:?}")))
+    }
+    fn tile_usage(&self, tsmod: &str, name: &str) -> Result<Vec<String>> {
+        let mut titles = self
+            .pages
+            .borrow()
+            .iter()
+            .filter(|(_, text)| text.contains(name) && text.contains(tsmod))
+            .map(|(title, _)| title.clone())
+            .collect::<Vec<_>>();
+        titles.sort();
+        Ok(titles)
+    }
+    fn translate_tile(
+        &self,
+        id: u64,
+        lang: &str,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<()> {
+        if !self.tiles.borrow().iter().any(|(_, tile)| tile.id == id) {
+            return Err(Error::Response(format!("no tile with id {id}")));
+        }
+        let mut translations = self.translations.borrow_mut();
+        let translation = translations.entry((id, lang.to_owned())).or_default();
+        translation.0 = name.to_owned();
+        if let Some(description) = description {
+            translation.1 = description.to_owned();
+        }
+        Ok(())
+    }
+    fn add_tiles(&self, tsmod: &str, tiles: &[(&str, TilePos)]) -> Result<()> {
+        for &(name, pos) in tiles {
+            self.insert_tile(tsmod, name, pos);
+        }
+        if self.lost_adds.get() > 0 {
+            self.lost_adds.set(self.lost_adds.get() - 1);
+            return Err(Error::Response("connection lost".into()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use super::TilePos;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// The plan for the part of an update that modifies the wiki, along with how much of it has
+/// been carried out. It is saved after every step so an interrupted update can be resumed
+/// without choosing new positions for the added tiles.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(super) struct Journal {
+    /// Tilesheet images to upload, which are kept in the base folder until the update finishes.
+    pub(super) layers: Vec<String>,
+    pub(super) uploaded: Vec<String>,
+    /// The new list of sizes, set when sizes were added or removed and cleared once the wiki
+    /// has it.
+    #[serde(default)]
+    pub(super) sizes: Option<Vec<u32>>,
+    pub(super) renamed: Vec<(u64, String, String)>,
+    pub(super) renamed_done: usize,
+    /// Tiles to move, which journals from before repacking existed do not have.
+    #[serde(default)]
+    pub(super) moved: Vec<(u64, String, TilePos)>,
+    #[serde(default)]
+    pub(super) moved_done: usize,
+    pub(super) deleted: Vec<(u64, String)>,
+    pub(super) deleted_chunks: usize,
+    /// Tiles to add, along with the positions they were placed at in the uploaded images.
+    pub(super) added: Vec<(String, TilePos)>,
+    pub(super) added_chunks: usize,
+    /// Tilesheet images left without tiles by repacking, which are deleted last.
+    #[serde(default)]
+    pub(super) emptied: Vec<String>,
+    #[serde(default)]
+    pub(super) emptied_done: usize,
+}
+impl Journal {
+    fn path(base: &Path, name: &str) -> PathBuf {
+        base.join(name).join("journal.json")
+    }
+    /// Loads the journal of an unfinished update, if there is one.
+    pub(super) fn load(base: &Path, name: &str) -> Result<Option<Journal>> {
+        let path = Journal::path(base, name);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::Io(path, e)),
+        };
+        let journal = serde_json::from_reader(file)
+            .map_err(|e| Error::Invalid(format!("corrupt journal {}: {e}", path.display())))?;
+        Ok(Some(journal))
+    }
+    /// Writes the journal to a temporary file first so a crash never leaves it half written.
+    pub(super) fn save(&self, base: &Path, name: &str) -> Result<()> {
+        let path = Journal::path(base, name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(Error::io(dir))?;
+        }
+        let temp = path.with_extension("json.tmp");
+        let file = File::create(&temp).map_err(Error::io(&temp))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| Error::Io(temp.clone(), e.into()))?;
+        fs::rename(&temp, &path).map_err(Error::io(&path))
+    }
+    pub(super) fn remove(base: &Path, name: &str) -> Result<()> {
+        let path = Journal::path(base, name);
+        fs::remove_file(&path).map_err(Error::io(&path))
+    }
+}
@@ -0,0 +1,281 @@
+use super::source::TileImage;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Display names for a mod's textures, read from its language files and item and block models.
+#[derive(Debug, Default)]
+pub(super) struct Names {
+    /// English display names by translation key.
+    lang: HashMap<String, String>,
+    /// Display names in every other language, by language and translation key.
+    foreign: HashMap<String, HashMap<String, String>>,
+    /// Namespaces that language files were found in.
+    namespaces: Vec<String>,
+    /// Translation keys of the models that use each texture as their only texture.
+    models: HashMap<String, Vec<String>>,
+}
+enum Asset {
+    /// A language file along with its namespace and language, such as `de_de`.
+    Json(String, String),
+    Lang(String, String),
+    /// A model along with the translation key of the item or block it belongs to.
+    Model(String),
+}
+fn asset(path: &str) -> Option<Asset> {
+    let rest = path.strip_prefix("assets/")?;
+    let (namespace, rest) = rest.split_once('/')?;
+    if let Some(file) = rest.strip_prefix("lang/") {
+        let file = file.to_lowercase();
+        let (language, extension) = file.rsplit_once('.')?;
+        let (namespace, language) = (namespace.to_owned(), language.to_owned());
+        return match extension {
+            "json" => Some(Asset::Json(namespace, language)),
+            "lang" => Some(Asset::Lang(namespace, language)),
+            _ => None,
+        };
+    }
+    let rest = rest.strip_prefix("models/")?;
+    let (folder, id) = rest.split_once('/')?;
+    let kind = match folder {
+        "item" | "items" => "item",
+        "block" | "blocks" => "block",
+        _ => return None,
+    };
+    let id = id.strip_suffix(".json")?.replace('/', ".");
+    Some(Asset::Model(format!("{kind}.{namespace}.{id}")))
+}
+/// Returns the resource location of a texture, such as `modid:item/iron_ingot`, from its path
+/// inside a jar or resource pack.
+pub(super) fn texture_id(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("assets/")?;
+    let (namespace, rest) = rest.split_once('/')?;
+    let texture = rest.strip_prefix("textures/")?.strip_suffix(".png")?;
+    Some(format!("{namespace}:{texture}"))
+}
+impl Names {
+    /// Whether the file at this path is a language file or model that names are read from.
+    pub(super) fn wants(path: &str) -> bool {
+        asset(path).is_some()
+    }
+    pub(super) fn add(&mut self, path: &str, data: &[u8]) {
+        let result = match asset(path) {
+            Some(Asset::Json(namespace, language)) => self.add_json(namespace, language, data),
+            Some(Asset::Lang(namespace, language)) => {
+                self.add_lang(namespace, language, data);
+                Ok(())
+            }
+            Some(Asset::Model(key)) => self.add_model(key, data),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            println!("WARNING: Failed to read {path}: {e}");
+        }
+    }
+    /// The translations of a language, noting the namespace when they are the English ones.
+    fn table(&mut self, namespace: String, language: String) -> &mut HashMap<String, String> {
+        if language != "en_us" {
+            return self.foreign.entry(language).or_default();
+        }
+        if !self.namespaces.contains(&namespace) {
+            self.namespaces.push(namespace);
+            self.namespaces.sort();
+        }
+        &mut self.lang
+    }
+    fn add_json(
+        &mut self,
+        namespace: String,
+        language: String,
+        data: &[u8],
+    ) -> Result<(), serde_json::Error> {
+        let lang: HashMap<String, Value> = serde_json::from_slice(data)?;
+        let table = self.table(namespace, language);
+        for (key, value) in lang {
+            if let Value::String(value) = value {
+                table.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+    /// Reads a `key=value` language file as used before 1.13.
+    fn add_lang(&mut self, namespace: String, language: String, data: &[u8]) {
+        let data = String::from_utf8_lossy(data);
+        let table = self.table(namespace, language);
+        for line in data.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                table.insert(key.to_owned(), value.to_owned());
+            }
+        }
+    }
+    fn add_model(&mut self, key: String, data: &[u8]) -> Result<(), serde_json::Error> {
+        let model: Value = serde_json::from_slice(data)?;
+        let textures = match model.get("textures").and_then(Value::as_object) {
+            Some(textures) => textures,
+            None => return Ok(()),
+        };
+        let mut found = Vec::new();
+        for (name, texture) in textures {
+            let texture = match texture.as_str() {
+                Some(texture) if name != "particle" && !texture.starts_with('#') => texture,
+                _ => continue,
+            };
+            let texture = if texture.contains(':') {
+                texture.to_owned()
+            } else {
+                format!("minecraft:{texture}")
+            };
+            if !found.contains(&texture) {
+                found.push(texture);
+            }
+        }
+        // A model made of several textures, such as a block with distinct sides, does not tell
+        // which of them should carry the name.
+        if let [texture] = &found[..] {
+            let keys = self.models.entry(texture.clone()).or_default();
+            keys.push(key);
+            keys.sort();
+        }
+        Ok(())
+    }
+    /// The number of translations that were loaded.
+    pub(super) fn translations(&self) -> usize {
+        self.lang.len()
+    }
+    /// The languages other than English that translations were loaded for.
+    pub(super) fn languages(&self) -> Vec<&str> {
+        let mut languages = self.foreign.keys().map(|x| &**x).collect::<Vec<_>>();
+        languages.sort();
+        languages
+    }
+    /// The translation keys of each English display name.
+    pub(super) fn keys_by_name(&self) -> HashMap<&str, Vec<&str>> {
+        let mut keys: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (key, name) in &self.lang {
+            keys.entry(name).or_default().push(key);
+        }
+        for keys in keys.values_mut() {
+            keys.sort();
+        }
+        keys
+    }
+    /// Looks up a translation key in another language.
+    pub(super) fn translation(&self, language: &str, key: &str) -> Option<&str> {
+        self.foreign.get(language)?.get(key).map(|x| &**x)
+    }
+    /// Finds the display name for a tile image, preferring the models that use the texture and
+    /// falling back to a translation key named after the file.
+    pub(super) fn name(&self, image: &TileImage) -> Option<&str> {
+        if let Some(keys) = image.texture.as_ref().and_then(|x| self.models.get(x)) {
+            if let Some(name) = keys.iter().find_map(|key| self.lang.get(key)) {
+                return Some(name);
+            }
+        }
+        let stem = &image.stem;
+        let namespaces = match image.texture.as_ref().and_then(|x| x.split_once(':')) {
+            Some((namespace, _)) => vec![namespace],
+            None => self.namespaces.iter().map(|x| &**x).collect(),
+        };
+        let mut keys = Vec::new();
+        for namespace in namespaces {
+            keys.push(format!("item.{namespace}.{stem}"));
+            keys.push(format!("block.{namespace}.{stem}"));
+            keys.push(format!("item.{namespace}.{stem}.name"));
+            keys.push(format!("tile.{namespace}.{stem}.name"));
+        }
+        keys.push(format!("item.{stem}.name"));
+        keys.push(format!("tile.{stem}.name"));
+        keys.iter().find_map(|key| self.lang.get(key)).map(|x| &**x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(stem: &str, texture: Option<&str>) -> TileImage {
+        TileImage::in_memory(stem, texture, Vec::new())
+    }
+    #[test]
+    fn asset_kinds() {
+        assert!(matches!(
+            asset("assets/mod/lang/de_DE.lang"),
+            Some(Asset::Lang(namespace, language)) if namespace == "mod" && language == "de_de"
+        ));
+        assert!(matches!(
+            asset("assets/mod/lang/en_us.json"),
+            Some(Asset::Json(_, language)) if language == "en_us"
+        ));
+        assert!(matches!(
+            asset("assets/mod/models/blocks/ore/copper.json"),
+            Some(Asset::Model(key)) if key == "block.mod.ore.copper"
+        ));
+        assert!(asset("assets/mod/models/entity/cow.json").is_none());
+        assert!(asset("assets/mod/textures/item/ingot.png").is_none());
+        assert!(asset("data/mod/lang/en_us.json").is_none());
+    }
+    #[test]
+    fn texture_ids() {
+        assert_eq!(
+            texture_id("assets/mod/textures/item/iron_ingot.png").as_deref(),
+            Some("mod:item/iron_ingot")
+        );
+        assert_eq!(texture_id("assets/mod/models/item/iron_ingot.json"), None);
+    }
+    #[test]
+    fn names_from_models_and_keys() {
+        let mut names = Names::default();
+        names.add(
+            "assets/mod/lang/en_us.json",
+            br#"{"item.mod.gadget": "Fancy Gadget", "item.mod.iron_ingot": "Iron Ingot"}"#,
+        );
+        names.add(
+            "assets/mod/models/item/gadget.json",
+            br#"{"textures": {"layer0": "mod:item/gadget_base"}}"#,
+        );
+        names.add(
+            "assets/old/lang/en_US.lang",
+            b"# comment\ntile.copper.name=Copper\n",
+        );
+        assert_eq!(names.translations(), 3);
+        let gadget = image("gadget_base", Some("mod:item/gadget_base"));
+        assert_eq!(names.name(&gadget), Some("Fancy Gadget"));
+        let ingot = image("iron_ingot", Some("mod:item/iron_ingot"));
+        assert_eq!(names.name(&ingot), Some("Iron Ingot"));
+        assert_eq!(names.name(&image("copper", None)), Some("Copper"));
+        assert_eq!(names.name(&image("missing", None)), None);
+    }
+    #[test]
+    fn models_with_several_textures_give_no_name() {
+        let mut names = Names::default();
+        names.add(
+            "assets/mod/lang/en_us.json",
+            br#"{"block.mod.machine": "Machine"}"#,
+        );
+        names.add(
+            "assets/mod/models/block/machine.json",
+            br#"{"textures": {"top": "mod:block/machine_top", "side": "mod:block/machine_side"}}"#,
+        );
+        let top = image("machine_top", Some("mod:block/machine_top"));
+        assert_eq!(names.name(&top), None);
+    }
+    #[test]
+    fn other_languages_are_kept_apart() {
+        let mut names = Names::default();
+        names.add(
+            "assets/mod/lang/en_us.json",
+            br#"{"item.mod.gear": "Gear"}"#,
+        );
+        names.add(
+            "assets/mod/lang/de_de.json",
+            br#"{"item.mod.gear": "Zahnrad"}"#,
+        );
+        assert_eq!(names.translations(), 1);
+        assert_eq!(names.languages(), ["de_de"]);
+        assert_eq!(names.translation("de_de", "item.mod.gear"), Some("Zahnrad"));
+        assert_eq!(names.keys_by_name()["Gear"], ["item.mod.gear"]);
+    }
+}
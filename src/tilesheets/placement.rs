@@ -0,0 +1,209 @@
+use super::{source::TileImage, TilePos};
+
+/// The order in which the cells of a layer are filled.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Layout {
+    /// Square shells growing out from the top left corner, so every layer stays square.
+    #[default]
+    Shell,
+    /// Rows of a fixed number of cells, top to bottom.
+    Rows(u32),
+}
+/// Which tiles are kept next to each other when new tiles are placed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Grouping {
+    #[default]
+    None,
+    /// Tiles whose names start with the same word.
+    Prefix,
+    /// Tiles whose files are in the same folder.
+    Folder,
+}
+/// How tiles are assigned positions in the tilesheet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Placement {
+    pub layout: Layout,
+    pub grouping: Grouping,
+    /// The most cells along either side of a layer before tiles spill into the next layer.
+    pub max_size: u32,
+}
+impl Default for Placement {
+    fn default() -> Placement {
+        Placement {
+            layout: Layout::Shell,
+            grouping: Grouping::None,
+            max_size: 64,
+        }
+    }
+}
+impl Layout {
+    /// Parses `shell` or `rows:<width>`.
+    pub fn parse(layout: &str) -> Option<Layout> {
+        match layout.split_once(':') {
+            None if layout == "shell" => Some(Layout::Shell),
+            Some(("rows", width)) => match width.parse() {
+                Ok(width) if width > 0 => Some(Layout::Rows(width)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+impl Grouping {
+    pub fn parse(grouping: &str) -> Option<Grouping> {
+        match grouping {
+            "none" => Some(Grouping::None),
+            "prefix" => Some(Grouping::Prefix),
+            "folder" => Some(Grouping::Folder),
+            _ => None,
+        }
+    }
+}
+fn isqrt(n: u64) -> u64 {
+    let mut r = (n as f64).sqrt() as u64;
+    while r * r > n {
+        r -= 1;
+    }
+    while (r + 1) * (r + 1) <= n {
+        r += 1;
+    }
+    r
+}
+impl Placement {
+    fn width(&self) -> u32 {
+        match self.layout {
+            Layout::Shell => self.max_size,
+            Layout::Rows(width) => width.min(self.max_size),
+        }
+    }
+    fn cells(&self) -> u64 {
+        self.width() as u64 * self.max_size as u64
+    }
+    /// The position that comes at this index in fill order.
+    pub(super) fn position(&self, index: u64) -> TilePos {
+        let z = (index / self.cells()) as u32;
+        let i = index % self.cells();
+        match self.layout {
+            Layout::Shell => {
+                let ring = isqrt(i);
+                let k = (i - ring * ring) as u32;
+                let ring = ring as u32;
+                if k < ring {
+                    TilePos { x: k, y: ring, z }
+                } else {
+                    TilePos {
+                        x: ring,
+                        y: k - ring,
+                        z,
+                    }
+                }
+            }
+            Layout::Rows(_) => {
+                let width = self.width() as u64;
+                TilePos {
+                    x: (i % width) as u32,
+                    y: (i / width) as u32,
+                    z,
+                }
+            }
+        }
+    }
+    /// A key that sorts positions in fill order. Positions outside of the layout, left over from
+    /// another layout, come after every position inside it on the same layer.
+    pub(super) fn order(&self, TilePos { x, y, z }: TilePos) -> (u32, bool, u64) {
+        let (x, y) = (x as u64, y as u64);
+        match self.layout {
+            Layout::Shell if x.max(y) < self.max_size as u64 => {
+                let ring = x.max(y);
+                let k = if y == ring && x < ring { x } else { ring + y };
+                (z, false, ring * ring + k)
+            }
+            Layout::Rows(_) if x < self.width() as u64 && y < self.max_size as u64 => {
+                (z, false, y * self.width() as u64 + x)
+            }
+            _ => (z, true, y * self.max_size as u64 + x),
+        }
+    }
+    /// The key that tiles are grouped by, if any.
+    pub(super) fn group(&self, name: &str, image: Option<&TileImage>) -> String {
+        match self.grouping {
+            Grouping::None => String::new(),
+            Grouping::Prefix => name.split(' ').next().unwrap_or_default().to_owned(),
+            Grouping::Folder => image
+                .and_then(|image| image.origin.rsplit_once(&['/', '\\'][..]))
+                .map(|(folder, _)| folder.to_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn check_fill_order(placement: Placement) {
+        let mut seen = HashSet::new();
+        let mut last = None;
+        for index in 0..placement.cells() * 2 + 5 {
+            let pos = placement.position(index);
+            assert!(
+                pos.x < placement.width() && pos.y < placement.max_size,
+                "{pos:?}"
+            );
+            assert!(seen.insert(pos), "{pos:?} was placed twice");
+            let order = placement.order(pos);
+            assert!(!order.1);
+            assert!(last < Some(order), "{pos:?} is out of order");
+            last = Some(order);
+        }
+    }
+    #[test]
+    fn shell_fill_order() {
+        let placement = Placement {
+            max_size: 5,
+            ..Placement::default()
+        };
+        check_fill_order(placement);
+        let first = (0..4).map(|i| placement.position(i)).collect::<Vec<_>>();
+        let expected = [(0, 0), (0, 1), (1, 0), (1, 1)];
+        for (pos, (x, y)) in first.iter().zip(expected) {
+            assert_eq!((pos.x, pos.y, pos.z), (x, y, 0));
+        }
+    }
+    #[test]
+    fn rows_fill_order() {
+        let placement = Placement {
+            layout: Layout::Rows(3),
+            max_size: 4,
+            ..Placement::default()
+        };
+        check_fill_order(placement);
+        let pos = placement.position(4);
+        assert_eq!((pos.x, pos.y, pos.z), (1, 1, 0));
+        let pos = placement.position(12);
+        assert_eq!((pos.x, pos.y, pos.z), (0, 0, 1));
+    }
+    #[test]
+    fn positions_outside_the_layout_come_last() {
+        let placement = Placement {
+            layout: Layout::Rows(3),
+            max_size: 4,
+            ..Placement::default()
+        };
+        let outside = placement.order(TilePos { x: 3, y: 0, z: 0 });
+        let inside = placement.order(TilePos { x: 2, y: 3, z: 0 });
+        assert!(inside < outside);
+        assert!(outside < placement.order(TilePos { x: 0, y: 0, z: 1 }));
+    }
+    #[test]
+    fn parse_layout_and_grouping() {
+        assert_eq!(Layout::parse("shell"), Some(Layout::Shell));
+        assert_eq!(Layout::parse("rows:16"), Some(Layout::Rows(16)));
+        assert_eq!(Layout::parse("rows:0"), None);
+        assert_eq!(Layout::parse("rows"), None);
+        assert_eq!(Layout::parse("columns:4"), None);
+        assert_eq!(Grouping::parse("folder"), Some(Grouping::Folder));
+        assert_eq!(Grouping::parse("suffix"), None);
+    }
+}
@@ -0,0 +1,158 @@
+use super::{backend::Backend, prompt_continue, TilesheetManager};
+use crate::error::{Error, Result};
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder, RgbaImage};
+use std::{fmt::Write as _, fs};
+
+/// Tiles as they look in the largest tilesheet, collected during an update for the preview.
+#[derive(Default)]
+pub(super) struct Preview {
+    pub(super) added: Vec<(String, RgbaImage)>,
+    /// Missing tiles, cut from the tilesheet before it was changed.
+    pub(super) missing: Vec<(String, RgbaImage)>,
+    /// Tiles whose pixels changed, with their old and new images.
+    pub(super) changed: Vec<(String, RgbaImage, RgbaImage)>,
+}
+const STYLE: &str = "body { font-family: sans-serif; }
+.tiles { display: flex; flex-wrap: wrap; gap: 8px; }
+.tile { text-align: center; font-size: 12px; width: 140px; }
+.check { background: repeating-conic-gradient(#ccc 0 25%, #fff 0 50%) 0 0 / 16px 16px; }
+.tile img { width: 64px; height: 64px; image-rendering: pixelated; margin: 2px; }
+.layer { position: relative; display: inline-block; margin: 4px; }
+.layer img { display: block; image-rendering: pixelated; }
+.layer::after { content: ''; position: absolute; inset: 0; pointer-events: none;
+  background-image: linear-gradient(to right, rgba(255, 0, 0, 0.4) 1px, transparent 1px),
+    linear-gradient(to bottom, rgba(255, 0, 0, 0.4) 1px, transparent 1px);
+  background-size: var(--cell) var(--cell); }";
+
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+/// Encodes an image as a PNG data URI so the report needs no other files.
+fn data_uri(img: &RgbaImage, name: &str) -> Result<String> {
+    let mut png = Vec::new();
+    let (w, h) = img.dimensions();
+    PngEncoder::new(&mut png)
+        .write_image(img.as_raw(), w, h, ColorType::Rgba8)
+        .map_err(Error::image(name))?;
+    Ok(format!("data:image/png;base64,{}", base64(&png)))
+}
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+fn tile_section(html: &mut String, title: &str, tiles: &[(String, RgbaImage)]) -> Result<()> {
+    let _ = writeln!(
+        html,
+        "<h2>{title} ({})</h2>\n<div class=\"tiles\">",
+        tiles.len()
+    );
+    for (name, img) in tiles {
+        let _ = writeln!(
+            html,
+            "<div class=\"tile\"><img class=\"check\" src=\"{}\"><br>{}</div>",
+            data_uri(img, name)?,
+            escape(name)
+        );
+    }
+    html.push_str("</div>\n");
+    Ok(())
+}
+impl<B: Backend> TilesheetManager<B> {
+    /// Writes preview.html, showing the added, missing and changed tiles along with every layer
+    /// of the largest rebuilt tilesheet.
+    pub(super) fn write_preview(&self) -> Result<()> {
+        let preview = &self.preview;
+        let mut html = String::new();
+        let _ = writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Tilesheet preview for {name}</title>\n<style>\n{STYLE}\n</style>\n</head>\n\
+             <body>\n<h1>Tilesheet preview for {name}</h1>",
+            name = escape(&self.name)
+        );
+        tile_section(&mut html, "Added tiles", &preview.added)?;
+        tile_section(&mut html, "Missing tiles", &preview.missing)?;
+        let _ = writeln!(
+            html,
+            "<h2>Changed tiles ({})</h2>\n<div class=\"tiles\">",
+            preview.changed.len()
+        );
+        for (name, old, new) in &preview.changed {
+            let _ = writeln!(
+                html,
+                "<div class=\"tile\"><img class=\"check\" src=\"{}\" title=\"Old\">\
+                 <img class=\"check\" src=\"{}\" title=\"New\"><br>{}</div>",
+                data_uri(old, name)?,
+                data_uri(new, name)?,
+                escape(name)
+            );
+        }
+        html.push_str("</div>\n");
+        if let Some(sheet) = self.tilesheets.iter().max_by_key(|sheet| sheet.size) {
+            html.push_str("<h2>Layers</h2>\n");
+            for (z, layer) in sheet.layers.iter().enumerate() {
+                let name = format!("Tilesheet {} {} {}.png", self.name, sheet.size, z);
+                let changed = if sheet.changed[z] { " (changed)" } else { "" };
+                let _ = writeln!(
+                    html,
+                    "<h3>{}{changed}</h3>\n<div class=\"layer check\" style=\"--cell: {}px\">\
+                     <img src=\"{}\"></div>",
+                    escape(&name),
+                    sheet.size,
+                    data_uri(layer, &name)?
+                );
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        let path = self.base.join("preview.html");
+        fs::write(&path, html).map_err(Error::io(&path))?;
+        println!("Wrote preview to {}", path.display());
+        Ok(())
+    }
+    /// Writes the preview and waits for the user to approve the rebuilt tilesheet.
+    pub(super) fn confirm_preview(&self) -> Result<()> {
+        self.write_preview()?;
+        if self.options.yes {
+            return Ok(());
+        }
+        println!("Please review the rebuilt tilesheet in preview.html.");
+        println!("When you are done, please enter \"continue\".");
+        prompt_continue()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_vectors() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+}
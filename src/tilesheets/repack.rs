@@ -0,0 +1,63 @@
+use super::{backend::Backend, placement::Grouping, Sheet, TilesheetManager};
+use crate::error::Result;
+use image::RgbaImage;
+use std::{collections::HashMap, mem::take};
+
+impl<B: Backend> TilesheetManager<B> {
+    /// Moves every tile into the first free positions, rebuilding the layers to match and
+    /// recording the tiles that moved. Tiles keep their order unless they are grouped. Layers
+    /// left without tiles at the end are dropped, and their images deleted from the wiki.
+    pub(super) fn repack(&mut self) -> Result<()> {
+        println!("Repacking tilesheet.");
+        let placement = self.options.placement;
+        let mut images = HashMap::new();
+        if placement.grouping == Grouping::Folder {
+            images.extend(self.local_tiles()?);
+        }
+        let mut tiles = self
+            .tiles
+            .drain()
+            .map(|(name, tile)| {
+                let group = placement.group(&name, images.get(&name));
+                ((group, placement.order(tile.pos)), name, tile)
+            })
+            .collect::<Vec<_>>();
+        tiles.sort_by(|(a, a_name, _), (b, b_name, _)| (a, a_name).cmp(&(b, b_name)));
+        self.entries.clear();
+        self.missing.clear();
+        self.next = 0;
+        let old_sheets = take(&mut self.tilesheets);
+        self.tilesheets = old_sheets
+            .iter()
+            .map(|sheet| Sheet::new(sheet.size))
+            .collect();
+        for (_, name, tile) in tiles {
+            let pos = self.lookup(&name);
+            self.tiles.get_mut(&name).unwrap().id = tile.id;
+            for (sheet, old) in self.tilesheets.iter_mut().zip(&old_sheets) {
+                let img = old
+                    .tile(tile.pos)
+                    .unwrap_or_else(|| RgbaImage::new(old.size, old.size));
+                sheet.place(pos, &img);
+            }
+            if let Some(id) = tile.id.filter(|_| pos != tile.pos) {
+                self.moved.push((id, name, pos));
+            }
+        }
+        for (sheet, old) in self.tilesheets.iter_mut().zip(&old_sheets) {
+            for (z, layer) in sheet.layers.iter().enumerate() {
+                sheet.changed[z] = old.layers.get(z) != Some(layer);
+            }
+            for z in sheet.layers.len()..old.layers.len() {
+                self.emptied
+                    .push(format!("Tilesheet {} {} {}.png", self.name, sheet.size, z));
+            }
+        }
+        println!(
+            "Moved {} tiles, dropping {} tilesheet images that are left empty.",
+            self.moved.len(),
+            self.emptied.len()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use super::{backend::Backend, source::TileImage, write_lines, TilesheetManager};
+use crate::{decode_srgb, encode_srgb, error::Result, resize, Filter, FloatImage};
+use image::{Rgba, RgbaImage};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+/// Tiles that are at least this similar are suggested as renames.
+const MIN_SIMILARITY: f32 = 0.9;
+/// Tiles that are at least this similar are reported as near duplicates.
+const NEAR_DUPLICATE: f32 = 0.97;
+/// Tiles whose average colours differ by more than this in any channel are never alike, as the
+/// hash only sees brightness and recoloured tiles would match perfectly otherwise.
+const MAX_COLOR_DIFFERENCE: u8 = 24;
+
+/// A difference hash of the tile's brightness, composited over grey so that transparent areas
+/// count as well.
+fn hash(img: &FloatImage) -> u64 {
+    let small = resize(img, 9, 8, Filter::Box);
+    let brightness = |x: u32, y: u32| {
+        let p = small[(x, y)];
+        0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2] + 0.5 * (1. - p[3])
+    };
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = (hash << 1) | (brightness(x, y) < brightness(x + 1, y)) as u64;
+        }
+    }
+    hash
+}
+/// What tiles are compared by: the shape of their brightness and their average colour.
+#[derive(Clone, Copy, Debug)]
+struct Fingerprint {
+    hash: u64,
+    color: Rgba<u8>,
+}
+fn fingerprint(img: &FloatImage) -> Fingerprint {
+    Fingerprint {
+        hash: hash(img),
+        color: encode_srgb(&resize(img, 1, 1, Filter::Box))[(0, 0)],
+    }
+}
+fn similarity(a: Fingerprint, b: Fingerprint) -> f32 {
+    let mut channels = a.color.0.iter().zip(b.color.0);
+    let same_color = channels.all(|(&x, y)| x.abs_diff(y) <= MAX_COLOR_DIFFERENCE);
+    if !same_color {
+        return 0.;
+    }
+    1. - (a.hash ^ b.hash).count_ones() as f32 / 64.
+}
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+impl<B: Backend> TilesheetManager<B> {
+    /// Compares the added tiles against the missing tiles in the largest imported tilesheet and
+    /// writes the pairs that look alike to suggested_renames.txt, in the form used by
+    /// renames.txt.
+    pub(super) fn suggest_renames(&self, added: &[(String, TileImage)]) -> Result<()> {
+        let path = self.base.join("suggested_renames.txt");
+        let sheet = match self.tilesheets.iter().max_by_key(|sheet| sheet.size) {
+            Some(sheet) if !added.is_empty() && !self.missing.is_empty() => sheet,
+            // Clear the suggestions of an earlier run.
+            _ => return write_lines(&path, Vec::<String>::new()),
+        };
+        let mut missing = Vec::new();
+        for name in &self.missing {
+            let tile = self.tiles[name].pos;
+            if let Some(img) = sheet.tile(tile) {
+                missing.push((name, fingerprint(&decode_srgb(&img))));
+            }
+        }
+        let mut pairs = Vec::new();
+        for (new, image) in added {
+            let new_print = fingerprint(&self.prepare(image)?);
+            for &(old, old_print) in &missing {
+                let score = similarity(old_print, new_print);
+                if score >= MIN_SIMILARITY {
+                    pairs.push((score, old, new));
+                }
+            }
+        }
+        // Most similar first, so that each tile is paired with its closest match.
+        pairs.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| (a.1, a.2).cmp(&(b.1, b.2)))
+        });
+        let mut lines = Vec::new();
+        let mut used = Vec::new();
+        for (score, old, new) in pairs {
+            if used.contains(&old) || used.contains(&new) {
+                continue;
+            }
+            used.push(old);
+            used.push(new);
+            lines.push(format!("# {:.0}% similar", score * 100.));
+            lines.push(format!("{old} => {new}"));
+        }
+        write_lines(&path, &lines)?;
+        if !lines.is_empty() {
+            println!(
+                "Found {} likely renames, review them in suggested_renames.txt.",
+                lines.len() / 2
+            );
+        }
+        Ok(())
+    }
+    /// Groups the local tiles, along with the registered tiles that have no local file, first by
+    /// identical content and then by looking alike. The groups are written to duplicates.txt so
+    /// editors can decide whether any of them should be aliases. Returns the number of groups.
+    pub(super) fn report_duplicates(&self) -> Result<usize> {
+        println!("Looking for duplicate tiles.");
+        let size = self.tilesheets.iter().map(|sheet| sheet.size).max();
+        let mut images: Vec<(String, RgbaImage)> = Vec::new();
+        let mut local = HashSet::new();
+        for (name, image) in self.local_tiles()? {
+            let img = self.prepare(&image)?;
+            // Compare the tiles as they would appear in the tilesheet.
+            let size = size.unwrap_or_else(|| img.width());
+            let img = encode_srgb(&resize(&img, size, size, Filter::Box));
+            local.insert(name.clone());
+            images.push((name, img));
+        }
+        if let Some(sheet) = self
+            .tilesheets
+            .iter()
+            .find(|sheet| Some(sheet.size) == size)
+        {
+            let mut registered = self
+                .tiles
+                .iter()
+                .filter(|(name, _)| !local.contains(*name))
+                .collect::<Vec<_>>();
+            registered.sort_by_key(|(name, _)| *name);
+            for (name, tile) in registered {
+                if let Some(img) = sheet.tile(tile.pos) {
+                    images.push((name.clone(), img));
+                }
+            }
+        }
+        // Empty tiles are reported by verify instead.
+        images.retain(|(_, img)| img.pixels().any(|p| p[3] != 0));
+        let mut groups: Vec<(Vec<String>, Fingerprint)> = Vec::new();
+        let mut index = HashMap::new();
+        for (name, img) in images {
+            let mut hasher = DefaultHasher::new();
+            img.dimensions().hash(&mut hasher);
+            img.as_raw().hash(&mut hasher);
+            let i = *index.entry(hasher.finish()).or_insert_with(|| {
+                groups.push((Vec::new(), fingerprint(&decode_srgb(&img))));
+                groups.len() - 1
+            });
+            groups[i].0.push(name);
+        }
+        let mut parent = (0..groups.len()).collect::<Vec<_>>();
+        for i in 0..groups.len() {
+            for j in i + 1..groups.len() {
+                if similarity(groups[i].1, groups[j].1) >= NEAR_DUPLICATE {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[b] = a;
+                }
+            }
+        }
+        let mut similar: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut lines = Vec::new();
+        for (i, (names, _)) in groups.iter().enumerate() {
+            let mut names = names.clone();
+            names.sort();
+            if names.len() > 1 {
+                lines.push(format!("Identical: {}", names.join(", ")));
+            }
+            similar
+                .entry(root(&mut parent, i))
+                .or_default()
+                .push(names.join(", "));
+        }
+        for (_, mut members) in similar {
+            if members.len() > 1 {
+                members.sort();
+                lines.push(format!("Similar: {}", members.join(", ")));
+            }
+        }
+        lines.sort();
+        write_lines(&self.base.join("duplicates.txt"), &lines)?;
+        if !lines.is_empty() {
+            println!(
+                "Found {} groups of duplicate tiles, see duplicates.txt.",
+                lines.len()
+            );
+        }
+        Ok(lines.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    /// The same shading in the given colour.
+    fn tinted(color: [f32; 3]) -> FloatImage {
+        ImageBuffer::from_fn(16, 16, |x, y| {
+            let shade = 0.3 + 0.7 * ((x * 7 + y * 3) % 16) as f32 / 15.;
+            Rgba([color[0] * shade, color[1] * shade, color[2] * shade, 1.])
+        })
+    }
+
+    #[test]
+    fn recoloured_tiles_are_not_alike() {
+        let orange = tinted([1., 0.3, 0.02]);
+        let grey = tinted([0.4, 0.4, 0.4]);
+        let gold = tinted([1., 0.7, 0.05]);
+        // The brightness alone cannot tell them apart.
+        assert_eq!(hash(&orange), hash(&grey));
+        assert_eq!(hash(&orange), hash(&gold));
+        let prints = [fingerprint(&orange), fingerprint(&grey), fingerprint(&gold)];
+        for (i, &a) in prints.iter().enumerate() {
+            assert_eq!(similarity(a, a), 1.);
+            for &b in &prints[i + 1..] {
+                assert!(similarity(a, b) < MIN_SIMILARITY, "{a:?} {b:?}");
+            }
+        }
+    }
+    #[test]
+    fn slightly_changed_tiles_are_alike() {
+        let old = tinted([1., 0.3, 0.02]);
+        let mut new = old.clone();
+        new.put_pixel(4, 6, Rgba([0.9, 0.2, 0.02, 1.]));
+        assert!(similarity(fingerprint(&old), fingerprint(&new)) >= MIN_SIMILARITY);
+    }
+}
@@ -0,0 +1,99 @@
+use super::{backend::Backend, Sheet, TilesheetManager};
+use crate::{
+    decode_srgb,
+    error::{Error, Result},
+};
+use std::collections::HashMap;
+
+impl<B: Backend> TilesheetManager<B> {
+    /// Renders every registered tile at each added size and drops the removed sizes. Tiles are
+    /// rendered from their local file when there is one, and from the largest existing size
+    /// otherwise. Returns the names of the images of the removed sizes.
+    pub(super) fn change_sizes(&mut self, add: &[u32], remove: &[u32]) -> Result<Vec<String>> {
+        for size in add {
+            if self.tilesheets.iter().any(|sheet| sheet.size == *size) {
+                return Err(Error::Invalid(format!(
+                    "the tilesheet already has size {size}"
+                )));
+            }
+        }
+        for size in remove {
+            if !self.tilesheets.iter().any(|sheet| sheet.size == *size) {
+                return Err(Error::Invalid(format!("the tilesheet has no size {size}")));
+            }
+        }
+        let kept = self
+            .tilesheets
+            .iter()
+            .filter(|sheet| !remove.contains(&sheet.size))
+            .count();
+        if kept + add.len() == 0 {
+            return Err(Error::Invalid("a tilesheet needs at least one size".into()));
+        }
+        let mut sheets = add.iter().map(|&size| Sheet::new(size)).collect::<Vec<_>>();
+        if !sheets.is_empty() {
+            println!("Rendering tiles at sizes {add:?}.");
+            let local = self.local_tiles()?.into_iter().collect::<HashMap<_, _>>();
+            let largest = self.tilesheets.iter().max_by_key(|sheet| sheet.size);
+            let mut tiles = self.tiles.iter().collect::<Vec<_>>();
+            tiles.sort_by_key(|(name, _)| *name);
+            let (mut enlarged, mut skipped) = (0, Vec::new());
+            for (name, tile) in tiles {
+                let (img, stem) = match (local.get(name), largest) {
+                    (Some(image), _) => (self.prepare(image)?, Some(&*image.stem)),
+                    (None, Some(largest)) => match largest.tile(tile.pos) {
+                        Some(img) => {
+                            if add.iter().any(|&size| size > largest.size) {
+                                enlarged += 1;
+                            }
+                            (decode_srgb(&img), None)
+                        }
+                        None => {
+                            skipped.push(name);
+                            continue;
+                        }
+                    },
+                    (None, None) => {
+                        skipped.push(name);
+                        continue;
+                    }
+                };
+                let (img, filter, pixel_art) = self.scaling(img, stem);
+                for sheet in &mut sheets {
+                    sheet.insert(tile.pos, &img, filter, pixel_art);
+                }
+            }
+            // Every size has the same layers, even where a layer has no tiles left.
+            let layers = self.tilesheets.iter().map(|sheet| sheet.layers.len());
+            let layers = layers.max().unwrap_or(0);
+            for sheet in &mut sheets {
+                while sheet.layers.len() < layers {
+                    sheet.add_layer();
+                }
+            }
+            if enlarged > 0 {
+                println!(
+                    "WARNING: {enlarged} tiles have no local file and were enlarged from size {}.",
+                    largest.map_or(0, |sheet| sheet.size)
+                );
+            }
+            for name in skipped {
+                println!("WARNING: Tile {name:?} has no local file or image and was left empty.");
+            }
+        }
+        let mut unused = Vec::new();
+        for sheet in self.tilesheets.iter().filter(|s| remove.contains(&s.size)) {
+            for z in 0..sheet.layers.len() {
+                unused.push(format!("Tilesheet {} {} {}.png", self.name, sheet.size, z));
+            }
+        }
+        self.tilesheets
+            .retain(|sheet| !remove.contains(&sheet.size));
+        self.tilesheets.extend(sheets);
+        self.tilesheets.sort_by_key(|sheet| sheet.size);
+        let sizes: Vec<u32> = self.tilesheets.iter().map(|sheet| sheet.size).collect();
+        println!("New tilesheet sizes: {sizes:?}");
+        self.new_sizes = Some(sizes);
+        Ok(unused)
+    }
+}
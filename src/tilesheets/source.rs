@@ -0,0 +1,297 @@
+use super::{
+    animation::{self, Animation},
+    naming::{self, Names},
+};
+use crate::error::{Error, Result};
+use image::RgbaImage;
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+/// Where an update finds the images for a mod's tiles.
+#[derive(Clone, Debug, Default)]
+pub enum Source {
+    /// The PNGs in `tilesheets/<mod>/` and its subdirectories.
+    #[default]
+    Directory,
+    /// The textures inside a mod jar or resource pack zip.
+    Archive {
+        path: PathBuf,
+        filter: TextureFilter,
+    },
+}
+/// Which textures in an archive count as tiles.
+#[derive(Clone, Debug)]
+pub struct TextureFilter {
+    /// The asset namespaces to read, or all of them if empty.
+    pub namespaces: Vec<String>,
+    /// Folders under `assets/<namespace>/textures/` whose PNGs are tiles.
+    pub folders: Vec<String>,
+}
+impl Default for TextureFilter {
+    fn default() -> TextureFilter {
+        TextureFilter {
+            namespaces: Vec::new(),
+            folders: ["item", "block", "items", "blocks"]
+                .iter()
+                .map(|&folder| folder.to_owned())
+                .collect(),
+        }
+    }
+}
+impl TextureFilter {
+    /// Returns the file stem of an archive entry if it is a texture that should become a tile.
+    fn matches<'a>(&self, entry: &'a str) -> Option<&'a str> {
+        let rest = entry.strip_prefix("assets/")?;
+        let (namespace, rest) = rest.split_once('/')?;
+        let texture = rest.strip_prefix("textures/")?;
+        if !self.namespaces.is_empty() && !self.namespaces.iter().any(|n| n == namespace) {
+            return None;
+        }
+        let in_folder = self.folders.iter().any(|folder| {
+            texture
+                .strip_prefix(folder.trim_matches('/'))
+                .is_some_and(|x| x.starts_with('/'))
+        });
+        if !in_folder {
+            return None;
+        }
+        let file = texture.rsplit('/').next()?;
+        file.strip_suffix(".png")
+    }
+}
+enum ImageData {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+/// An image found for a tile.
+pub(super) struct TileImage {
+    /// The file name without its extension, which the tile's name is derived from.
+    pub(super) stem: String,
+    /// Where the image came from, for messages.
+    pub(super) origin: String,
+    /// The texture's resource location, if it sits in an `assets/` layout.
+    pub(super) texture: Option<String>,
+    data: ImageData,
+    /// The contents of the `.png.mcmeta` sidecar, if there is one.
+    mcmeta: Option<Vec<u8>>,
+}
+impl TileImage {
+    pub(super) fn load(&self) -> Result<RgbaImage> {
+        let img = match &self.data {
+            ImageData::Path(path) => image::open(path),
+            ImageData::Bytes(data) => image::load_from_memory(data),
+        };
+        Ok(img.map_err(Error::image(&self.origin))?.to_rgba8())
+    }
+    /// Reads the animation from the sidecar, if the texture has one.
+    pub(super) fn animation(&self) -> Result<Option<Animation>> {
+        match &self.mcmeta {
+            Some(data) => animation::parse_mcmeta(data)
+                .map_err(|e| Error::Invalid(format!("invalid animation for {}: {e}", self.origin))),
+            None => Ok(None),
+        }
+    }
+}
+#[cfg(test)]
+impl TileImage {
+    /// An image held in memory, for tests.
+    pub(super) fn in_memory(stem: &str, texture: Option<&str>, data: Vec<u8>) -> TileImage {
+        TileImage {
+            stem: stem.to_owned(),
+            origin: format!("{stem}.png"),
+            texture: texture.map(str::to_owned),
+            data: ImageData::Bytes(data),
+            mcmeta: None,
+        }
+    }
+}
+impl Source {
+    /// Finds every image that should become one of the mod's tiles.
+    pub(super) fn images(&self, base: &Path, name: &str) -> Result<Vec<TileImage>> {
+        match self {
+            Source::Directory => directory_images(&base.join(name)),
+            Source::Archive { path, filter } => archive_images(path, filter),
+        }
+    }
+    /// Reads the language files and models that display names are derived from.
+    pub(super) fn names(&self, base: &Path, name: &str) -> Result<Names> {
+        let mut names = Names::default();
+        match self {
+            Source::Directory => {
+                let dir = base.join(name);
+                for entry in WalkDir::new(&dir) {
+                    let entry = entry.map_err(|e| {
+                        let path = e.path().unwrap_or(&dir).to_owned();
+                        Error::Io(path, e.into())
+                    })?;
+                    let path = entry.path();
+                    let relative = relative_path(&dir, path);
+                    if !path.is_file() || !Names::wants(&relative) {
+                        continue;
+                    }
+                    let data = fs::read(path).map_err(Error::io(path))?;
+                    names.add(&relative, &data);
+                }
+            }
+            Source::Archive { path, .. } => {
+                let mut archive = open_archive(path)?;
+                for i in 0..archive.len() {
+                    let mut entry = archive
+                        .by_index(i)
+                        .map_err(|e| Error::Io(path.to_owned(), e.into()))?;
+                    if !entry.is_file() || !Names::wants(entry.name()) {
+                        continue;
+                    }
+                    let entry_name = entry.name().to_owned();
+                    let mut data = Vec::new();
+                    entry
+                        .read_to_end(&mut data)
+                        .map_err(Error::io(format!("{}!{entry_name}", path.display())))?;
+                    names.add(&entry_name, &data);
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+/// Returns the path of a file below `dir` with `/` separators, as it would appear in a jar.
+fn relative_path(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    let parts = relative
+        .components()
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    parts.join("/")
+}
+fn open_archive(path: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(path).map_err(Error::io(path))?;
+    ZipArchive::new(file).map_err(|e| Error::Io(path.to_owned(), e.into()))
+}
+fn directory_images(dir: &Path) -> Result<Vec<TileImage>> {
+    let mut images = Vec::new();
+    for entry in WalkDir::new(dir) {
+        let entry = entry.map_err(|e| {
+            let path = e.path().unwrap_or(dir).to_owned();
+            Error::Io(path, e.into())
+        })?;
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        if path.extension().and_then(|x| x.to_str()) != Some("png") {
+            continue;
+        }
+        let stem = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| Error::Invalid(format!("invalid file name {}", path.display())))?;
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".mcmeta");
+        let mcmeta = match fs::read(&sidecar) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(Error::Io(sidecar.into(), e)),
+        };
+        images.push(TileImage {
+            stem: stem.to_owned(),
+            origin: path.display().to_string(),
+            texture: naming::texture_id(&relative_path(dir, path)),
+            data: ImageData::Path(path.to_owned()),
+            mcmeta,
+        });
+    }
+    Ok(images)
+}
+fn archive_images(path: &Path, filter: &TextureFilter) -> Result<Vec<TileImage>> {
+    let mut archive = open_archive(path)?;
+    let mut images: Vec<TileImage> = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| Error::Io(path.to_owned(), e.into()))?;
+        if !entry.is_file() {
+            continue;
+        }
+        let entry_name = entry.name().to_owned();
+        let stem = match filter.matches(&entry_name) {
+            Some(stem) => stem.to_owned(),
+            None => continue,
+        };
+        let origin = format!("{}!{entry_name}", path.display());
+        if let Some(other) = images.iter().find(|image| image.stem == stem) {
+            println!(
+                "WARNING: Ignoring {origin} as it has the same name as {}",
+                other.origin
+            );
+            continue;
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(Error::io(&origin))?;
+        drop(entry);
+        let mcmeta = match archive.by_name(&format!("{entry_name}.mcmeta")) {
+            Ok(mut sidecar) => {
+                let mut mcmeta = Vec::new();
+                sidecar
+                    .read_to_end(&mut mcmeta)
+                    .map_err(Error::io(format!("{origin}.mcmeta")))?;
+                Some(mcmeta)
+            }
+            Err(_) => None,
+        };
+        images.push(TileImage {
+            stem,
+            texture: naming::texture_id(&entry_name),
+            origin,
+            data: ImageData::Bytes(data),
+            mcmeta,
+        });
+    }
+    println!("Found {} textures in {}", images.len(), path.display());
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_filter_matches_item_and_block_textures() {
+        let filter = TextureFilter::default();
+        assert_eq!(
+            filter.matches("assets/mod/textures/item/iron_ingot.png"),
+            Some("iron_ingot")
+        );
+        assert_eq!(
+            filter.matches("assets/mod/textures/blocks/ore/copper.png"),
+            Some("copper")
+        );
+        assert_eq!(filter.matches("assets/mod/textures/entity/cow.png"), None);
+        assert_eq!(filter.matches("assets/mod/textures/itemx/a.png"), None);
+        assert_eq!(
+            filter.matches("assets/mod/textures/item/a.png.mcmeta"),
+            None
+        );
+        assert_eq!(filter.matches("data/mod/textures/item/a.png"), None);
+    }
+    #[test]
+    fn filter_by_namespace_and_folder() {
+        let filter = TextureFilter {
+            namespaces: vec!["mod".to_owned()],
+            folders: vec!["/gui/icons/".to_owned()],
+        };
+        assert_eq!(
+            filter.matches("assets/mod/textures/gui/icons/gear.png"),
+            Some("gear")
+        );
+        assert_eq!(
+            filter.matches("assets/other/textures/gui/icons/gear.png"),
+            None
+        );
+        assert_eq!(filter.matches("assets/mod/textures/item/gear.png"), None);
+    }
+}
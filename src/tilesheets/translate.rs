@@ -0,0 +1,191 @@
+use super::{backend::Backend, naming::Names, prompt_continue, write_lines, TilesheetManager};
+use crate::error::Result;
+
+/// Suffixes of the translation keys that hold a tooltip for the key they are appended to.
+const DESCRIPTIONS: [&str; 3] = ["desc", "tooltip", "description"];
+
+/// A tile's display name and description in one of the wiki's languages.
+struct Translation {
+    id: u64,
+    tile: String,
+    lang: String,
+    name: String,
+    description: String,
+}
+/// Minecraft locales along with the MediaWiki language codes their wikis use. The region is only
+/// kept where MediaWiki has a separate language for it.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("ar_sa", "ar"),
+    ("bg_bg", "bg"),
+    ("ca_es", "ca"),
+    ("cs_cz", "cs"),
+    ("da_dk", "da"),
+    ("de_de", "de"),
+    ("el_gr", "el"),
+    ("es_es", "es"),
+    ("et_ee", "et"),
+    ("fi_fi", "fi"),
+    ("fr_fr", "fr"),
+    ("he_il", "he"),
+    ("hr_hr", "hr"),
+    ("hu_hu", "hu"),
+    ("id_id", "id"),
+    ("it_it", "it"),
+    ("ja_jp", "ja"),
+    ("ko_kr", "ko"),
+    ("lt_lt", "lt"),
+    ("lv_lv", "lv"),
+    ("nb_no", "nb"),
+    ("nl_nl", "nl"),
+    ("nn_no", "nn"),
+    ("pl_pl", "pl"),
+    ("pt_br", "pt-br"),
+    ("pt_pt", "pt"),
+    ("ro_ro", "ro"),
+    ("ru_ru", "ru"),
+    ("sk_sk", "sk"),
+    ("sl_si", "sl"),
+    ("sv_se", "sv"),
+    ("th_th", "th"),
+    ("tr_tr", "tr"),
+    ("uk_ua", "uk"),
+    ("vi_vn", "vi"),
+    ("zh_cn", "zh-cn"),
+    ("zh_hk", "zh-hk"),
+    ("zh_tw", "zh-tw"),
+];
+/// Converts a Minecraft locale such as `de_de` or `zh_cn` to a wiki language code such as `de`
+/// or `zh-cn`, if the locale has one.
+fn wiki_language(locale: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|&&(other, _)| other == locale)
+        .map(|&(_, code)| code)
+}
+impl<B: Backend> TilesheetManager<B> {
+    /// Matches the registered tiles to translation keys through their English names and collects
+    /// their names and descriptions in every other language. Translations that only repeat the
+    /// English name are left out.
+    fn translations(&self, names: &Names) -> Vec<Translation> {
+        let mut languages = Vec::new();
+        for locale in names.languages() {
+            match wiki_language(locale) {
+                Some(code) => languages.push((locale, code)),
+                None => println!("WARNING: No wiki language for {locale}, skipping it."),
+            }
+        }
+        let keys = names.keys_by_name();
+        let mut tiles = self
+            .tiles
+            .iter()
+            .filter_map(|(name, tile)| Some((name, tile.id?)))
+            .collect::<Vec<_>>();
+        tiles.sort();
+        let mut translations = Vec::new();
+        let mut unmatched = 0;
+        for (tile, id) in tiles {
+            let keys = match keys.get(&**tile) {
+                Some(keys) => keys,
+                None => {
+                    unmatched += 1;
+                    continue;
+                }
+            };
+            for &(language, code) in &languages {
+                let found = keys
+                    .iter()
+                    .find_map(|&key| Some((key, names.translation(language, key)?)));
+                let (key, name) = match found {
+                    Some(found) => found,
+                    None => continue,
+                };
+                let base = key.strip_suffix(".name").unwrap_or(key);
+                let description = DESCRIPTIONS
+                    .iter()
+                    .find_map(|suffix| names.translation(language, &format!("{base}.{suffix}")))
+                    .unwrap_or_default();
+                if name == tile && description.is_empty() {
+                    continue;
+                }
+                translations.push(Translation {
+                    id,
+                    tile: tile.clone(),
+                    lang: code.to_owned(),
+                    name: name.to_owned(),
+                    description: description.to_owned(),
+                });
+            }
+        }
+        if unmatched > 0 {
+            println!("WARNING: {unmatched} tiles have no English display name and were skipped.");
+        }
+        translations
+    }
+    /// Writes the translations of the registered tiles to translations.txt and, once they are
+    /// confirmed, sets them on the wiki. Setting a translation replaces the old one, so this can
+    /// simply be run again if it is interrupted.
+    pub(super) fn sync_translations(&self, names: &Names) -> Result<()> {
+        let languages = names.languages();
+        if languages.is_empty() {
+            println!("No translations found.");
+            return Ok(());
+        }
+        println!("Found translations for {}.", languages.join(", "));
+        let translations = self.translations(names);
+        let lines = translations.iter().map(|t| {
+            if t.description.is_empty() {
+                format!("{} {} {} => {}", t.id, t.lang, t.tile, t.name)
+            } else {
+                format!(
+                    "{} {} {} => {} ({})",
+                    t.id, t.lang, t.tile, t.name, t.description
+                )
+            }
+        });
+        let path = self.base.join("translations.txt");
+        write_lines(&path, lines)?;
+        println!(
+            "Wrote {} translations to {}",
+            translations.len(),
+            path.display()
+        );
+        if self.options.dry_run || translations.is_empty() {
+            return Ok(());
+        }
+        if !self.options.yes {
+            println!("Please check the translations in translations.txt.");
+            println!("When you are done, please enter \"continue\".");
+            prompt_continue()?;
+        }
+        println!("Translating tiles.");
+        for t in &translations {
+            // An empty description would erase one that editors translated on the wiki.
+            let description = Some(&*t.description).filter(|d| !d.is_empty());
+            self.wiki
+                .translate_tile(t.id, &t.lang, &t.name, description)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wiki_languages() {
+        assert_eq!(wiki_language("de_de"), Some("de"));
+        assert_eq!(wiki_language("ja_jp"), Some("ja"));
+        assert_eq!(wiki_language("ko_kr"), Some("ko"));
+        assert_eq!(wiki_language("uk_ua"), Some("uk"));
+        assert_eq!(wiki_language("zh_cn"), Some("zh-cn"));
+        assert_eq!(wiki_language("pt_br"), Some("pt-br"));
+        assert_eq!(wiki_language("lol_us"), None);
+    }
+    #[test]
+    fn languages_are_sorted_and_unique() {
+        for pair in LANGUAGES.windows(2) {
+            assert!(pair[0].0 < pair[1].0);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use super::{backend::Backend, TilePos, TilesheetManager};
+
+impl<B: Backend> TilesheetManager<B> {
+    /// Cross-checks the imported tiles against the imported tilesheet layers, printing every
+    /// problem found and returning how many there were.
+    pub(super) fn verify(&self) -> usize {
+        println!("Verifying tilesheet.");
+        let mut problems = 0;
+        let mut report = |problem: String| {
+            println!("{problem}");
+            problems += 1;
+        };
+        for (pos, first, second) in &self.overlaps {
+            let TilePos { x, y, z } = pos;
+            report(format!(
+                "Tiles {first:?} and {second:?} share the position {x} {y} {z}"
+            ));
+        }
+        let layer_counts = self
+            .tilesheets
+            .iter()
+            .map(|sheet| sheet.layers.len())
+            .collect::<Vec<_>>();
+        if layer_counts.windows(2).any(|w| w[0] != w[1]) {
+            for sheet in &self.tilesheets {
+                report(format!(
+                    "Size {} has {} layers, which differs from the other sizes",
+                    sheet.size,
+                    sheet.layers.len()
+                ));
+            }
+        }
+        let mut names = self.tiles.keys().collect::<Vec<_>>();
+        names.sort();
+        for sheet in &self.tilesheets {
+            let size = sheet.size;
+            for (z, layer) in sheet.layers.iter().enumerate() {
+                let (w, h) = layer.dimensions();
+                if w % size != 0 || h % size != 0 {
+                    report(format!(
+                        "Layer {z} of size {size} is {w}x{h}, which is not a multiple of {size}"
+                    ));
+                }
+            }
+            for name in &names {
+                let pos = self.tiles[*name].pos;
+                let TilePos { x, y, z } = pos;
+                match sheet.tile(pos) {
+                    None => report(format!(
+                        "Tile {name:?} at {x} {y} {z} lies outside the size {size} layer"
+                    )),
+                    Some(img) if img.pixels().all(|p| p[3] == 0) => report(format!(
+                        "Tile {name:?} at {x} {y} {z} is fully transparent at size {size}"
+                    )),
+                    Some(_) => (),
+                }
+            }
+            for (z, layer) in sheet.layers.iter().enumerate() {
+                let (w, h) = layer.dimensions();
+                for y in 0..h / size {
+                    for x in 0..w / size {
+                        let z = z as u32;
+                        let pos = TilePos { x, y, z };
+                        if self.entries.contains_key(&pos) {
+                            continue;
+                        }
+                        if sheet.tile(pos).unwrap().pixels().any(|p| p[3] != 0) {
+                            report(format!(
+                                "Cell {x} {y} {z} is not empty at size {size} but has no tile"
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        problems
+    }
+}